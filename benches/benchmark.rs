@@ -1,6 +1,5 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use istanbul_sourcemap::{transform_istanbul_coverage, CoverageMap, SourceMapStore};
-use serde_json;
 
 fn benchmark_transform_coverage(c: &mut Criterion) {
     let test_data = r#"{
@@ -6,6 +6,7 @@ package main
 
 // Function declarations
 char* transform_coverage_ffi(const char* input);
+char* transform_coverage_with_error_ffi(const char* input, int* error_code, char** error_message);
 void free_string(char* ptr);
 const char* get_version();
 */
@@ -20,8 +21,15 @@ func TransformCoverage(input string) (string, error) {
 	cInput := C.CString(input)
 	defer C.free(unsafe.Pointer(cInput))
 
-	cResult := C.transform_coverage_ffi(cInput)
+	var cCode C.int
+	var cMessage *C.char
+	cResult := C.transform_coverage_with_error_ffi(cInput, &cCode, &cMessage)
 	if cResult == nil {
+		if cMessage != nil {
+			defer C.free_string(cMessage)
+			return "", fmt.Errorf("failed to transform coverage data (code %d): %s",
+				int(cCode), C.GoString(cMessage))
+		}
 		return "", fmt.Errorf("failed to transform coverage data")
 	}
 	defer C.free_string(cResult)
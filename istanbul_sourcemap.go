@@ -9,6 +9,7 @@ package istanbul_sourcemap
 
 // Function declarations
 char* transform_coverage_ffi(const char* input);
+char* transform_coverage_with_error_ffi(const char* input, int* error_code, char** error_message);
 void free_string(char* ptr);
 const char* get_version();
 */
@@ -19,6 +20,18 @@ import (
 	"unsafe"
 )
 
+// TransformError is returned when the Rust library fails to transform coverage data
+type TransformError struct {
+	// Code is the library's error code, e.g. 1 for invalid JSON, 4 for an invalid
+	// VLQ mapping, 100 for null input or 101 for input that is not valid UTF-8
+	Code    int
+	Message string
+}
+
+func (e *TransformError) Error() string {
+	return fmt.Sprintf("failed to transform coverage data (code %d): %s", e.Code, e.Message)
+}
+
 // IstanbulSourceMap provides methods to transform Istanbul coverage data
 type IstanbulSourceMap struct{}
 
@@ -32,9 +45,18 @@ func (ism *IstanbulSourceMap) TransformCoverage(input string) (string, error) {
 	cInput := C.CString(input)
 	defer C.free(unsafe.Pointer(cInput))
 
-	cResult := C.transform_coverage_ffi(cInput)
+	// Errors are returned through out-params rather than thread-local state,
+	// since goroutines may move between OS threads across cgo calls
+	var cCode C.int
+	var cMessage *C.char
+	cResult := C.transform_coverage_with_error_ffi(cInput, &cCode, &cMessage)
 	if cResult == nil {
-		return "", fmt.Errorf("failed to transform coverage data")
+		err := &TransformError{Code: int(cCode)}
+		if cMessage != nil {
+			err.Message = C.GoString(cMessage)
+			C.free_string(cMessage)
+		}
+		return "", err
 	}
 	defer C.free_string(cResult)
 
@@ -0,0 +1,86 @@
+use crate::report::{DropReason, EntryKind};
+use thiserror::Error;
+
+/// Errors returned by the library
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Coverage data or a source map is not valid JSON
+    #[error("Invalid JSON: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+
+    /// A source map file could not be parsed
+    #[error("Failed to parse source map [{path}]: {source}")]
+    InvalidSourceMap {
+        path: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// The source map is not a version 3 map
+    #[error("Unsupported source map version {0}, expected 3")]
+    UnsupportedVersion(u32),
+
+    /// A `mappings` segment is not valid base64 VLQ
+    ///
+    /// `line` and `index` are the 0-based generated line and the position of
+    /// the segment within it.
+    #[error("Invalid VLQ segment [{segment}] at line {line}, segment {index}")]
+    Vlq {
+        line: usize,
+        index: usize,
+        segment: String,
+    },
+
+    /// A `data:` URL is malformed or its payload cannot be decoded
+    #[error("Invalid data URL: {0}")]
+    InvalidDataUrl(String),
+
+    /// Nothing in the coverage of a generated file could be mapped
+    #[error("File [{path}] ignored, nothing could be mapped")]
+    UnmappableFile { path: String },
+
+    /// A file could not be read
+    #[error("Failed to read [{path}]: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A path or URL cannot be resolved to a readable file
+    #[error("Cannot resolve [{path}]: {reason}")]
+    PathResolution { path: String, reason: String },
+
+    /// A coverage entry could not be mapped in strict mode
+    ///
+    /// `line` and `column` are the start of the entry's generated location.
+    #[error("Cannot map {kind} [{id}] of [{path}] at line {line}, column {column}: {reason}")]
+    UnmappedEntry {
+        path: String,
+        kind: EntryKind,
+        id: String,
+        line: u32,
+        column: u32,
+        reason: DropReason,
+    },
+}
+
+impl Error {
+    /// Stable numeric code of the error kind, as reported over FFI
+    pub fn code(&self) -> i32 {
+        match self {
+            Error::InvalidJson(_) => 1,
+            Error::InvalidSourceMap { .. } => 2,
+            Error::UnsupportedVersion(_) => 3,
+            Error::Vlq { .. } => 4,
+            Error::InvalidDataUrl(_) => 5,
+            Error::UnmappableFile { .. } => 6,
+            Error::Io { .. } => 7,
+            Error::PathResolution { .. } => 8,
+            Error::UnmappedEntry { .. } => 9,
+        }
+    }
+}
+
+/// Result type of the library
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -1,6 +1,53 @@
-use crate::transform_istanbul_coverage;
+use crate::{transform_istanbul_coverage, Error};
+use std::cell::RefCell;
 use std::ffi::{CStr, CString};
-use std::os::raw::c_char;
+use std::os::raw::{c_char, c_int};
+
+/// Error code of a null `input` pointer
+pub const ERROR_NULL_INPUT: c_int = 100;
+/// Error code of an `input` that is not valid UTF-8
+pub const ERROR_INVALID_UTF8: c_int = 101;
+/// Error code of a result that contains a NUL byte
+pub const ERROR_INTERIOR_NUL: c_int = 102;
+
+thread_local! {
+    /// Code and message of the last failed call on this thread
+    static LAST_ERROR: RefCell<Option<(c_int, CString)>> = const { RefCell::new(None) };
+}
+
+/// Convert an error message into a C string, replacing NUL bytes
+fn error_message(message: String) -> CString {
+    CString::new(message.replace('\0', " ")).unwrap_or_default()
+}
+
+fn set_last_error(error: Option<(c_int, CString)>) {
+    LAST_ERROR.with(|cell| *cell.borrow_mut() = error);
+}
+
+/// Transform the coverage JSON at `input` into a C string
+///
+/// # Safety
+///
+/// `input` must be null or a valid null-terminated C string.
+unsafe fn transform(input: *const c_char) -> Result<CString, (c_int, CString)> {
+    if input.is_null() {
+        return Err((ERROR_NULL_INPUT, error_message("Input is null".into())));
+    }
+    let input = CStr::from_ptr(input).to_str().map_err(|e| {
+        (
+            ERROR_INVALID_UTF8,
+            error_message(format!("Input is not valid UTF-8: {e}")),
+        )
+    })?;
+    let result = transform_istanbul_coverage(input)
+        .map_err(|e: Error| (e.code(), error_message(e.to_string())))?;
+    CString::new(result).map_err(|e| {
+        (
+            ERROR_INTERIOR_NUL,
+            error_message(format!("Result contains a NUL byte: {e}")),
+        )
+    })
+}
 
 /// Transform Istanbul coverage data (C FFI interface)
 ///
@@ -10,24 +57,79 @@ use std::os::raw::c_char;
 /// The caller must ensure that:
 /// - `input` is a valid null-terminated C string
 /// - The returned pointer must be freed using `free_string`
+///
+/// On failure null is returned, and `get_last_error_code` and
+/// `get_last_error_message` describe why when called on the same OS thread.
+/// Callers that cannot guarantee this, such as Go without
+/// `runtime.LockOSThread`, should use `transform_coverage_with_error_ffi`.
 #[no_mangle]
 pub unsafe extern "C" fn transform_coverage_ffi(input: *const c_char) -> *mut c_char {
-    if input.is_null() {
-        return std::ptr::null_mut();
+    match transform(input) {
+        Ok(result) => {
+            set_last_error(None);
+            result.into_raw()
+        }
+        Err(error) => {
+            set_last_error(Some(error));
+            std::ptr::null_mut()
+        }
     }
+}
 
-    let c_str = match CStr::from_ptr(input).to_str() {
-        Ok(s) => s,
-        Err(_) => return std::ptr::null_mut(),
+/// Transform Istanbul coverage data, reporting errors through out-params
+///
+/// # Safety
+///
+/// The caller must ensure that:
+/// - `input` is a valid null-terminated C string
+/// - `error_code` and `error_message` are null or valid for writes
+/// - The returned pointer and `*error_message` must be freed using
+///   `free_string`
+///
+/// On success the result is returned and `*error_code` is set to 0 and
+/// `*error_message` to null. On failure null is returned, `*error_code` is
+/// set to the code of [`Error::code`] or one of the `ERROR_*` constants, and
+/// `*error_message` to a description of the error.
+#[no_mangle]
+pub unsafe extern "C" fn transform_coverage_with_error_ffi(
+    input: *const c_char,
+    error_code: *mut c_int,
+    error_message: *mut *mut c_char,
+) -> *mut c_char {
+    let (result, code, message) = match transform(input) {
+        Ok(result) => (result.into_raw(), 0, std::ptr::null_mut()),
+        Err((code, message)) => (std::ptr::null_mut(), code, message.into_raw()),
     };
-
-    match transform_istanbul_coverage(c_str) {
-        Ok(result) => match CString::new(result) {
-            Ok(c_string) => c_string.into_raw(),
-            Err(_) => std::ptr::null_mut(),
-        },
-        Err(_) => std::ptr::null_mut(),
+    if !error_code.is_null() {
+        *error_code = code;
+    }
+    if !error_message.is_null() {
+        *error_message = message;
+    } else if !message.is_null() {
+        free_string(message);
     }
+    result
+}
+
+/// Code of the last error on this thread, or 0 when the last call succeeded
+///
+/// The codes are those of [`Error::code`] and the `ERROR_*` constants.
+#[no_mangle]
+pub extern "C" fn get_last_error_code() -> c_int {
+    LAST_ERROR.with(|cell| cell.borrow().as_ref().map_or(0, |(code, _)| *code))
+}
+
+/// Message of the last error on this thread, or null when there is none
+///
+/// The string is owned by the library and valid until the next call to
+/// `transform_coverage_ffi` on the same thread; it must not be freed.
+#[no_mangle]
+pub extern "C" fn get_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|cell| {
+        cell.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |(_, message)| message.as_ptr())
+    })
 }
 
 /// Free a string allocated by the library
@@ -1,11 +1,15 @@
-use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
+pub mod error;
 pub mod ffi;
+pub mod pathutils;
+pub mod report;
 pub mod sourcemap;
 pub mod transformer;
 
+pub use error::{Error, Result};
+pub use report::*;
 pub use sourcemap::*;
 pub use transformer::*;
 
@@ -13,14 +17,77 @@ pub use transformer::*;
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Position {
     pub line: u32,
+    /// Column, or [`Position::END_OF_LINE`] which is written as `null`
+    ///
+    /// A missing column is read as [`Position::END_OF_LINE`] as well.
+    #[serde(default = "end_of_line", with = "end_of_line_column")]
     pub column: u32,
 }
 
+impl Position {
+    /// Column of a range that extends to the end of its line.
+    ///
+    /// Istanbul uses `Infinity` here, which `JSON.stringify` writes as `null`.
+    pub const END_OF_LINE: u32 = u32::MAX;
+}
+
+fn end_of_line() -> u32 {
+    Position::END_OF_LINE
+}
+
+mod end_of_line_column {
+    use super::Position;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(column: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        if *column == Position::END_OF_LINE {
+            serializer.serialize_none()
+        } else {
+            serializer.serialize_u32(*column)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        Ok(Option::<u32>::deserialize(deserializer)?.unwrap_or(Position::END_OF_LINE))
+    }
+}
+
 /// Location range in source code
+///
+/// Istanbul omits `end` on some implicit branch locations; it is read as the
+/// end of the start line.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "PartialLocation")]
 pub struct Location {
     pub start: Position,
     pub end: Position,
+    /// Set on code excluded from coverage by an ignore hint
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip: bool,
+}
+
+/// Location as written by istanbul, with optional parts
+#[derive(Deserialize)]
+struct PartialLocation {
+    start: Position,
+    end: Option<Position>,
+    #[serde(default)]
+    skip: bool,
+}
+
+impl From<PartialLocation> for Location {
+    fn from(loc: PartialLocation) -> Self {
+        let start = loc.start;
+        let end = loc.end.unwrap_or(Position {
+            line: start.line,
+            column: Position::END_OF_LINE,
+        });
+        Self {
+            start,
+            end,
+            skip: loc.skip,
+        }
+    }
 }
 
 /// Function metadata
@@ -29,6 +96,15 @@ pub struct FunctionMeta {
     pub name: String,
     pub decl: Location,
     pub loc: Location,
+    /// Start line of `loc`, kept for legacy reporters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    /// Set on code excluded from coverage by an ignore hint
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip: bool,
+    /// Fields not modeled above, preserved as they are
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 /// Branch metadata
@@ -38,48 +114,158 @@ pub struct BranchMeta {
     pub branch_type: String,
     pub loc: Location,
     pub locations: Vec<Location>,
+    /// Start line of `loc`, kept for legacy reporters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    /// Set on code excluded from coverage by an ignore hint
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip: bool,
+    /// Fields not modeled above, preserved as they are
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
+/// Unknown fields of a coverage object
+pub type Extra = serde_json::Map<String, serde_json::Value>;
+
 /// Source map structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SourceMap {
     pub version: u32,
+    #[serde(default)]
     pub sources: Vec<String>,
+    #[serde(default)]
     pub names: Vec<String>,
+    #[serde(default)]
     pub mappings: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub file: Option<String>,
     #[serde(rename = "sourceRoot", skip_serializing_if = "Option::is_none")]
     pub source_root: Option<String>,
     #[serde(rename = "sourcesContent", skip_serializing_if = "Option::is_none")]
-    pub sources_content: Option<Vec<String>>,
+    pub sources_content: Option<Vec<Option<String>>>,
+    /// Indices into `sources` of third-party or generated code
+    #[serde(rename = "ignoreList", skip_serializing_if = "Option::is_none")]
+    pub ignore_list: Option<Vec<u32>>,
+    /// Pre-standard name of `ignoreList`, used when it is absent
+    #[serde(
+        rename = "x_google_ignoreList",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub x_google_ignore_list: Option<Vec<u32>>,
+    /// Sections of an index source map, used instead of `mappings`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sections: Option<Vec<SourceMapSection>>,
+}
+
+/// Section of an index source map
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceMapSection {
+    pub offset: SectionOffset,
+    pub map: SourceMap,
+}
+
+/// Generated position at which a section starts (0-based line and column)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SectionOffset {
+    pub line: u32,
+    pub column: u32,
 }
 
 /// File coverage data
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileCoverage {
     pub path: String,
-    #[serde(rename = "statementMap")]
+    #[serde(rename = "statementMap", serialize_with = "by_index::serialize")]
     pub statement_map: HashMap<String, Location>,
-    #[serde(rename = "fnMap")]
+    #[serde(rename = "fnMap", serialize_with = "by_index::serialize")]
     pub fn_map: HashMap<String, FunctionMeta>,
-    #[serde(rename = "branchMap")]
+    #[serde(rename = "branchMap", serialize_with = "by_index::serialize")]
     pub branch_map: HashMap<String, BranchMeta>,
-    pub s: HashMap<String, u32>,      // statement hits
-    pub f: HashMap<String, u32>,      // function hits
+    #[serde(serialize_with = "by_index::serialize")]
+    pub s: HashMap<String, u32>, // statement hits
+    #[serde(serialize_with = "by_index::serialize")]
+    pub f: HashMap<String, u32>, // function hits
+    #[serde(serialize_with = "by_index::serialize")]
     pub b: HashMap<String, Vec<u32>>, // branch hits
+    /// Truthy-value hits of logical expression branches, emitted by
+    /// babel-plugin-istanbul with `reportLogic`
+    #[serde(
+        rename = "bT",
+        serialize_with = "by_index::serialize_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub b_t: Option<HashMap<String, Vec<u32>>>,
     #[serde(rename = "inputSourceMap", skip_serializing_if = "Option::is_none")]
     pub input_source_map: Option<SourceMap>,
+    /// Original source text carried over from `sourcesContent`, for
+    /// reporters when the file is not available on disk
+    #[serde(rename = "sourceContent", skip_serializing_if = "Option::is_none")]
+    pub source_content: Option<String>,
+    /// Set on generated files kept in the output although nothing in them
+    /// could be mapped, see [`UnmappedFilePolicy::KeepWithMarker`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unmapped: bool,
+    /// Hash of the instrumented source, used by nyc to detect changes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Version of the istanbul coverage schema
+    #[serde(rename = "_coverageSchema", skip_serializing_if = "Option::is_none")]
+    pub coverage_schema: Option<String>,
+    /// Set on files added by `all` without being loaded by the tests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub all: Option<bool>,
+    /// Fields not modeled above, preserved as they are
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Coverage map (file path -> file coverage), ordered by path
+pub type CoverageMap = BTreeMap<String, FileCoverage>;
+
+/// Entries of an index map (`"0"`, `"1"`, ...) in numeric key order
+///
+/// Keys that are not numbers sort after the numeric ones.
+pub(crate) fn sorted_by_index<V>(map: &HashMap<String, V>) -> Vec<(&String, &V)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by_key(|(key, _)| (key.parse::<u64>().unwrap_or(u64::MAX), *key));
+    entries
 }
 
-/// Coverage map (file path -> file coverage)
-pub type CoverageMap = HashMap<String, FileCoverage>;
+/// Serialize index maps in numeric key order, so output is byte-stable
+mod by_index {
+    use super::sorted_by_index;
+    use serde::{Serialize, Serializer};
+    use std::collections::HashMap;
+
+    pub fn serialize<S: Serializer, V: Serialize>(
+        map: &HashMap<String, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(sorted_by_index(map))
+    }
+
+    pub fn serialize_option<S: Serializer, V: Serialize>(
+        map: &Option<HashMap<String, V>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match map {
+            Some(map) => serialize(map, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+}
 
 /// Mapping result from source map
 #[derive(Debug, Clone)]
 pub struct Mapping {
     pub source: String,
     pub loc: Location,
+    /// Whether the source is in the map's ignore list
+    pub ignored: bool,
+    /// Original name of the segment starting exactly at the start of the
+    /// location
+    pub name: Option<String>,
 }
 
 /// Create a new source map store
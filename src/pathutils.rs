@@ -0,0 +1,188 @@
+//! Path helpers for source map sources
+//!
+//! Sources may be filesystem paths or URLs; both are handled with `/` as the
+//! separator, following the `source-map` JavaScript library.
+
+/// Whether `path` starts with a URL scheme such as `https:` or `webpack:`
+///
+/// Single-letter schemes are treated as Windows drive letters.
+pub fn is_url(path: &str) -> bool {
+    match path.find(':') {
+        Some(end) if end >= 2 => {
+            let scheme = &path[..end];
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        _ => false,
+    }
+}
+
+/// Whether `path` is an absolute filesystem path (POSIX or Windows)
+pub fn is_absolute(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    path.starts_with(['/', '\\'])
+        || (bytes.len() >= 3
+            && bytes[0].is_ascii_alphabetic()
+            && bytes[1] == b':'
+            && matches!(bytes[2], b'/' | b'\\'))
+}
+
+/// Split a hierarchical URL into `scheme://host` and the remaining path
+fn split_origin(path: &str) -> Option<(&str, &str)> {
+    if !is_url(path) {
+        return None;
+    }
+    let after_scheme = path.find("://")? + 3;
+    let path_start = path[after_scheme..]
+        .find('/')
+        .map_or(path.len(), |i| after_scheme + i);
+    Some(path.split_at(path_start))
+}
+
+/// Normalize `.` and `..` segments and duplicate separators
+///
+/// Filesystem paths accept both `/` and `\` and are joined with `/`.
+/// Leading `..` segments of relative paths are kept. The path part of
+/// hierarchical URLs is normalized; other URLs (e.g. `data:`) are returned
+/// unchanged.
+pub fn normalize(path: &str) -> String {
+    let (origin, rest) = match split_origin(path) {
+        Some(parts) => parts,
+        None if is_url(path) => return path.to_string(),
+        None => ("", path),
+    };
+    let absolute = rest.starts_with('/') || (origin.is_empty() && rest.starts_with('\\'));
+
+    let mut parts: Vec<&str> = Vec::new();
+    let separators: &[char] = if origin.is_empty() {
+        &['/', '\\']
+    } else {
+        &['/']
+    };
+    for part in rest.split(separators) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if parts.last().is_some_and(|last| *last != "..") {
+                    parts.pop();
+                } else if !absolute {
+                    parts.push("..");
+                }
+            }
+            _ => parts.push(part),
+        }
+    }
+
+    let mut normalized = origin.to_string();
+    if absolute {
+        normalized.push('/');
+    }
+    normalized.push_str(&parts.join("/"));
+    if normalized.is_empty() && !path.is_empty() {
+        normalized.push('.');
+    }
+    normalized
+}
+
+/// Directory part of `path`, or an empty string when it has none
+pub fn dirname(path: &str) -> &str {
+    match path.rfind(['/', '\\']) {
+        Some(0) => &path[..1],
+        Some(index) => &path[..index],
+        None => "",
+    }
+}
+
+/// Join `path` onto `root` with URL semantics
+///
+/// URLs in `path` are returned as is. Absolute paths replace the path of a
+/// URL root and are otherwise returned unchanged.
+pub fn join(root: &str, path: &str) -> String {
+    if root.is_empty() || is_url(path) {
+        return normalize(path);
+    }
+    if path.is_empty() {
+        return normalize(root);
+    }
+    if path.starts_with('/') {
+        return match split_origin(root) {
+            Some((origin, _)) => normalize(&format!("{origin}{path}")),
+            None => normalize(path),
+        };
+    }
+    if root.ends_with('/') {
+        normalize(&format!("{root}{path}"))
+    } else {
+        normalize(&format!("{root}/{path}"))
+    }
+}
+
+/// Make `path` relative to `base` when it lies inside it
+///
+/// Both paths are normalized first; paths outside `base` are returned
+/// normalized but otherwise unchanged.
+pub fn relative_to_base(base: &str, path: &str) -> String {
+    let base = normalize(base);
+    let path = normalize(path);
+    if base == "." && !is_absolute(&path) && !is_url(&path) {
+        return path;
+    }
+    match path.strip_prefix(base.as_str()) {
+        Some("") => ".".to_string(),
+        Some(rest) if base.ends_with('/') => rest.to_string(),
+        Some(rest) if rest.starts_with('/') => rest[1..].to_string(),
+        _ => path,
+    }
+}
+
+/// Relative path from directory `from` to `to`
+///
+/// Falls back to `to` when the two cannot be related, e.g. when only one of
+/// them is absolute or either is a URL.
+pub fn relative(from: &str, to: &str) -> String {
+    let from = normalize(from);
+    let to = normalize(to);
+    if is_url(&from) || is_url(&to) || is_absolute(&from) != is_absolute(&to) {
+        return to;
+    }
+
+    let components = |path: &str| -> Vec<String> {
+        path.split('/')
+            .filter(|part| !part.is_empty() && *part != ".")
+            .map(str::to_string)
+            .collect()
+    };
+    let from = components(&from);
+    let to = components(&to);
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut parts = vec![".."; from.len() - common];
+    parts.extend(to[common..].iter().map(String::as_str));
+    if parts.is_empty() {
+        ".".to_string()
+    } else {
+        parts.join("/")
+    }
+}
+
+/// Resolve a source map source against the map's `sourceRoot`
+pub fn resolve_source(source_root: Option<&str>, source: &str) -> String {
+    join(source_root.unwrap_or_default(), source)
+}
+
+/// Convert bundler and `file:` URLs into filesystem paths
+///
+/// `webpack://<namespace>/<path>` becomes `<path>` and `file://<host>/<path>`
+/// becomes `/<path>`; other sources are returned unchanged.
+pub fn url_to_path(source: &str) -> String {
+    if let Some(rest) = source.strip_prefix("webpack://") {
+        let resource = rest.split_once('/').map_or("", |(_, resource)| resource);
+        return normalize(resource);
+    }
+    if let Some(rest) = source.strip_prefix("file://") {
+        return normalize(&rest[rest.find('/').unwrap_or(rest.len())..]);
+    }
+    source.to_string()
+}
@@ -0,0 +1,253 @@
+use crate::{sorted_by_index, FileCoverage, Location};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Kind of a diagnostic raised while transforming coverage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DiagnosticKind {
+    /// Nothing in the coverage of a generated file could be mapped
+    FileIgnored,
+    /// A source map was found but could not be decoded
+    InvalidSourceMap,
+}
+
+/// Warning raised while transforming coverage
+///
+/// Diagnostics are collected in the [`TransformReport`] instead of being
+/// printed, so stdout stays free for the coverage output.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    /// Generated file, or source map, the diagnostic is about
+    pub file: String,
+    pub message: String,
+}
+
+/// Kind of a coverage entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum EntryKind {
+    Statement,
+    Function,
+    Branch,
+}
+
+impl fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EntryKind::Statement => "statement",
+            EntryKind::Function => "function",
+            EntryKind::Branch => "branch",
+        })
+    }
+}
+
+/// Why a coverage entry was dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DropReason {
+    /// No original position was found for the location
+    Unmappable,
+    /// Parts of the entry map to different original sources
+    MultipleSources,
+    /// The entry maps to a source in the map's ignore list
+    Ignored,
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DropReason::Unmappable => "no original position found",
+            DropReason::MultipleSources => "it maps to multiple sources",
+            DropReason::Ignored => "its source is ignore-listed",
+        })
+    }
+}
+
+/// Coverage entry of a generated file that was dropped
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DroppedEntry {
+    pub kind: EntryKind,
+    /// Key of the entry in `statementMap`, `fnMap` or `branchMap`
+    pub id: String,
+    /// Generated location of the entry
+    pub loc: Location,
+    pub reason: DropReason,
+}
+
+/// Arm of a kept branch that was placed at the branch's location
+///
+/// Only produced with [`BranchArmPolicy::KeepAll`](crate::BranchArmPolicy::KeepAll).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlaceholderArm {
+    /// Key of the branch in `branchMap`
+    pub id: String,
+    /// Index of the arm in the branch's `locations`
+    pub arm: usize,
+    /// Generated location of the arm
+    pub loc: Location,
+    pub reason: DropReason,
+}
+
+/// Number of coverage entries of one kind by mapping outcome
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MappingCounts {
+    /// Entries mapped to an original source, including merged ones
+    pub mapped: usize,
+    /// Dropped because no original position was found
+    pub unmappable: usize,
+    /// Dropped because their parts map to different sources
+    #[serde(rename = "multiSource")]
+    pub multi_source: usize,
+    /// Dropped because they map to an ignore-listed source
+    pub ignored: usize,
+    /// Mapped entries merged into an existing entry at the same original
+    /// location
+    pub merged: usize,
+    /// Arms of mapped branches that could not be mapped themselves and were
+    /// kept at a placeholder location
+    #[serde(rename = "placeholderArms")]
+    pub placeholder_arms: usize,
+}
+
+impl MappingCounts {
+    /// Number of entries that were dropped for any reason
+    pub fn dropped(&self) -> usize {
+        self.unmappable + self.multi_source + self.ignored
+    }
+
+    pub(crate) fn record_mapped(&mut self, merged: bool) {
+        self.mapped += 1;
+        if merged {
+            self.merged += 1;
+        }
+    }
+
+    fn record_dropped(&mut self, reason: DropReason) {
+        match reason {
+            DropReason::Unmappable => self.unmappable += 1,
+            DropReason::MultipleSources => self.multi_source += 1,
+            DropReason::Ignored => self.ignored += 1,
+        }
+    }
+}
+
+/// Mapping statistics of a generated file
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FileReport {
+    pub statements: MappingCounts,
+    pub functions: MappingCounts,
+    pub branches: MappingCounts,
+    /// Entries that were dropped, in the order they were processed
+    pub dropped: Vec<DroppedEntry>,
+    /// Branch arms kept at a placeholder location, in the order they were
+    /// processed
+    #[serde(rename = "placeholderArms")]
+    pub placeholder_arms: Vec<PlaceholderArm>,
+}
+
+impl FileReport {
+    /// Report of a file none of whose coverage could be mapped
+    ///
+    /// Every statement, function and branch of `fc` is listed as dropped
+    /// with [`DropReason::Unmappable`].
+    pub fn unmapped(fc: &FileCoverage) -> Self {
+        let mut report = Self::default();
+        for (id, loc) in sorted_by_index(&fc.statement_map) {
+            report.record_dropped(EntryKind::Statement, id, loc, DropReason::Unmappable);
+        }
+        for (id, fn_meta) in sorted_by_index(&fc.fn_map) {
+            report.record_dropped(
+                EntryKind::Function,
+                id,
+                &fn_meta.loc,
+                DropReason::Unmappable,
+            );
+        }
+        for (id, branch_meta) in sorted_by_index(&fc.branch_map) {
+            report.record_dropped(
+                EntryKind::Branch,
+                id,
+                &branch_meta.loc,
+                DropReason::Unmappable,
+            );
+        }
+        report
+    }
+
+    pub(crate) fn record_dropped(
+        &mut self,
+        kind: EntryKind,
+        id: &str,
+        loc: &Location,
+        reason: DropReason,
+    ) {
+        let counts = match kind {
+            EntryKind::Statement => &mut self.statements,
+            EntryKind::Function => &mut self.functions,
+            EntryKind::Branch => &mut self.branches,
+        };
+        counts.record_dropped(reason);
+        self.dropped.push(DroppedEntry {
+            kind,
+            id: id.to_string(),
+            loc: loc.clone(),
+            reason,
+        });
+    }
+
+    pub(crate) fn record_placeholder_arm(
+        &mut self,
+        id: &str,
+        arm: usize,
+        loc: &Location,
+        reason: DropReason,
+    ) {
+        self.branches.placeholder_arms += 1;
+        self.placeholder_arms.push(PlaceholderArm {
+            id: id.to_string(),
+            arm,
+            loc: loc.clone(),
+            reason,
+        });
+    }
+
+    /// Number of statements, functions and branches that were mapped
+    pub fn mapped(&self) -> usize {
+        self.statements.mapped + self.functions.mapped + self.branches.mapped
+    }
+
+    /// Number of statements, functions and branches dropped because their
+    /// source is ignore-listed
+    pub fn ignored(&self) -> usize {
+        self.statements.ignored + self.functions.ignored + self.branches.ignored
+    }
+
+    /// Whether the file had coverage entries but none could be mapped
+    ///
+    /// Coverage dropped on purpose by the ignore list does not make a file
+    /// unmappable, and neither does a file without any entries.
+    pub fn is_unmappable(&self) -> bool {
+        let dropped =
+            self.statements.dropped() + self.functions.dropped() + self.branches.dropped();
+        self.mapped() == 0 && self.ignored() == 0 && dropped > 0
+    }
+}
+
+/// Outcome of transforming a coverage map
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TransformReport {
+    /// Statistics per generated file that had a source map, keyed by the
+    /// coverage map key
+    pub files: BTreeMap<String, FileReport>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl TransformReport {
+    pub(crate) fn warn(&mut self, kind: DiagnosticKind, file: &str, message: String) {
+        self.diagnostics.push(Diagnostic {
+            kind,
+            file: file.to_string(),
+            message,
+        });
+    }
+}
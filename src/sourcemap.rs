@@ -1,110 +1,408 @@
-use crate::{Location, Mapping, Position, SourceMap};
+use crate::{pathutils, report::DropReason, Error, Location, Mapping, Position, Result, SourceMap};
 use sourcemap::vlq::parse_vlq_segment;
+use std::collections::HashMap;
+
+/// Search bias used when a generated column has no exact segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bias {
+    /// Use the closest segment at or before the column
+    GreatestLowerBound,
+    /// Use the closest segment at or after the column
+    LeastUpperBound,
+}
 
 /// Source map decoder for handling VLQ mappings
+///
+/// Mappings are decoded once on construction into per-line and per-source
+/// indexes, so a decoder should be built once per source map and reused for
+/// every lookup against it.
 pub struct SourceMapDecoder {
-    source_map: SourceMap,
+    sources: Vec<String>,
+    /// Whether each source is in the map's `ignoreList`
+    ignored: Vec<bool>,
+    /// `sourcesContent` entry of each source
+    contents: Vec<Option<String>>,
+    names: Vec<String>,
+    source_indices: HashMap<String, usize>,
+    /// Decoded segments per generated line, sorted by generated column
+    lines: Vec<Vec<Segment>>,
+    /// Segments per source index, sorted by original position
+    original: Vec<Vec<OriginalEntry>>,
+}
+
+/// Decoded segment with source and name kept as indices
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    generated_column: u32,
+    source: Option<u32>,
+    original_line: u32,
+    original_column: u32,
+    name: Option<u32>,
+}
+
+/// Entry of the original-position index used for reverse lookups
+struct OriginalEntry {
+    original_line: u32,
+    original_column: u32,
+    generated_line: u32,
+    generated_column: u32,
 }
 
 impl SourceMapDecoder {
-    pub fn new(source_map: SourceMap) -> Self {
-        Self { source_map }
+    pub fn new(source_map: &SourceMap) -> Result<Self> {
+        let mut decoder = Self {
+            sources: Vec::new(),
+            ignored: Vec::new(),
+            contents: Vec::new(),
+            names: Vec::new(),
+            source_indices: HashMap::new(),
+            lines: Vec::new(),
+            original: Vec::new(),
+        };
+        decoder.add_map(source_map, 0, 0)?;
+        for segments in &mut decoder.lines {
+            segments.sort_by_key(|segment| segment.generated_column);
+        }
+
+        let mut original: Vec<Vec<OriginalEntry>> =
+            (0..decoder.sources.len()).map(|_| Vec::new()).collect();
+        for (generated_line, segments) in decoder.lines.iter().enumerate() {
+            for segment in segments {
+                if let Some(source) = segment.source {
+                    original[source as usize].push(OriginalEntry {
+                        original_line: segment.original_line,
+                        original_column: segment.original_column,
+                        generated_line: generated_line as u32,
+                        generated_column: segment.generated_column,
+                    });
+                }
+            }
+        }
+        for entries in &mut original {
+            entries.sort_by_key(|e| {
+                (
+                    e.original_line,
+                    e.original_column,
+                    e.generated_line,
+                    e.generated_column,
+                )
+            });
+        }
+        decoder.original = original;
+
+        Ok(decoder)
     }
 
-    /// Get original position for a generated position
-    pub fn get_original_position(&self, line: u32, column: u32) -> Option<OriginalPosition> {
-        // Simplified implementation - in production you'd need full VLQ decoding
-        if self.source_map.sources.is_empty() {
-            return None;
+    /// Add the segments of `source_map`, shifted by a generated offset
+    ///
+    /// Index maps are flattened here: every section is added at its offset,
+    /// with sources shared between sections resolved to a single index.
+    fn add_map(
+        &mut self,
+        source_map: &SourceMap,
+        line_offset: u32,
+        column_offset: u32,
+    ) -> Result<()> {
+        if source_map.version != 3 {
+            return Err(Error::UnsupportedVersion(source_map.version));
+        }
+        if let Some(sections) = &source_map.sections {
+            for section in sections {
+                let column_offset = if section.offset.line == 0 {
+                    column_offset + section.offset.column
+                } else {
+                    section.offset.column
+                };
+                self.add_map(
+                    &section.map,
+                    line_offset + section.offset.line,
+                    column_offset,
+                )?;
+            }
+            return Ok(());
+        }
+
+        let source_root = source_map.source_root.as_deref();
+        let source_ids: Vec<u32> = source_map
+            .sources
+            .iter()
+            .map(|source| self.intern_source(&pathutils::resolve_source(source_root, source)))
+            .collect();
+        let ignore_list = source_map
+            .ignore_list
+            .as_ref()
+            .or(source_map.x_google_ignore_list.as_ref());
+        for &index in ignore_list.into_iter().flatten() {
+            if let Some(&source) = source_ids.get(index as usize) {
+                self.ignored[source as usize] = true;
+            }
+        }
+        let contents = source_map.sources_content.iter().flatten();
+        for (&source, content) in source_ids.iter().zip(contents) {
+            if let Some(content) = content {
+                self.contents[source as usize].get_or_insert_with(|| content.clone());
+            }
+        }
+        let name_offset = self.names.len() as u32;
+        self.names.extend(source_map.names.iter().cloned());
+
+        for (index, segments) in decode_mappings(source_map)?.into_iter().enumerate() {
+            let line = line_offset as usize + index;
+            if self.lines.len() <= line {
+                self.lines.resize_with(line + 1, Vec::new);
+            }
+            for mut segment in segments {
+                if index == 0 {
+                    segment.generated_column += column_offset;
+                }
+                segment.source = segment.source.map(|source| source_ids[source as usize]);
+                segment.name = segment.name.map(|name| name + name_offset);
+                self.lines[line].push(segment);
+            }
         }
 
-        // Simple mapping to first source
+        Ok(())
+    }
+
+    fn intern_source(&mut self, source: &str) -> u32 {
+        if let Some(&index) = self.source_indices.get(source) {
+            return index as u32;
+        }
+        let index = self.sources.len();
+        self.sources.push(source.to_string());
+        self.ignored.push(false);
+        self.contents.push(None);
+        self.source_indices.insert(source.to_string(), index);
+        index as u32
+    }
+
+    /// Resolved sources of the map, joined with `sourceRoot`
+    pub fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
+    /// Whether `source` is listed in the map's `ignoreList`
+    /// (or `x_google_ignoreList`)
+    pub fn is_ignored(&self, source: &str) -> bool {
+        self.source_indices
+            .get(source)
+            .is_some_and(|&index| self.ignored[index])
+    }
+
+    /// Original text of `source` from the map's `sourcesContent`
+    pub fn source_content(&self, source: &str) -> Option<&str> {
+        self.source_content_at(*self.source_indices.get(source)?)
+    }
+
+    pub(crate) fn source_content_at(&self, index: usize) -> Option<&str> {
+        self.contents.get(index)?.as_deref()
+    }
+
+    /// Get original position for a generated position
+    ///
+    /// Lines are 1-based and columns 0-based, as in Istanbul locations. Only
+    /// segments on the same generated line are considered; `bias` selects the
+    /// nearest one before or after `column` when there is no exact match.
+    /// `None` is returned when there is no such segment or it carries no
+    /// source information. The returned source is joined with `sourceRoot`.
+    pub fn get_original_position(
+        &self,
+        line: u32,
+        column: u32,
+        bias: Bias,
+    ) -> Option<OriginalPosition> {
+        let segments = self.lines.get(line.checked_sub(1)? as usize)?;
+        let segment = match bias {
+            Bias::GreatestLowerBound => {
+                let index = segments.partition_point(|segment| segment.generated_column <= column);
+                &segments[index.checked_sub(1)?]
+            }
+            Bias::LeastUpperBound => {
+                let index = segments.partition_point(|segment| segment.generated_column < column);
+                segments.get(index)?
+            }
+        };
+
         Some(OriginalPosition {
-            source: self.source_map.sources[0].clone(),
-            line,
-            column,
-            name: None,
+            source: self.sources[segment.source? as usize].clone(),
+            line: segment.original_line + 1,
+            column: segment.original_column,
+            name: segment.name.map(|name| self.names[name as usize].clone()),
         })
     }
 
-    /// Parse VLQ mappings using sourcemap crate's VLQ decoder
-    pub fn parse_mappings(&self) -> Result<Vec<Vec<MappingSegment>>, Box<dyn std::error::Error>> {
-        let lines: Vec<&str> = self.source_map.mappings.split(';').collect();
-        let mut result = Vec::with_capacity(lines.len());
-
-        // State variables for VLQ decoding
-        let mut generated_column = 0i64;
-        let mut source_index = 0i64;
-        let mut original_line = 0i64;
-        let mut original_column = 0i64;
-        let mut name_index = 0i64;
-
-        for (line_index, line) in lines.iter().enumerate() {
-            if line_index > 0 {
-                generated_column = 0; // Reset for each line
+    /// Name of the segment starting exactly at a generated position
+    ///
+    /// Unlike [`Self::get_original_position`], no neighbouring segment is
+    /// used, so a position that only falls inside a named segment, such as
+    /// an anonymous function passed to a named call, gets no name.
+    pub(crate) fn name_at(&self, line: u32, column: u32) -> Option<String> {
+        let segments = self.lines.get(line.checked_sub(1)? as usize)?;
+        let index = segments.partition_point(|segment| segment.generated_column < column);
+        segments[index..]
+            .iter()
+            .take_while(|segment| segment.generated_column == column)
+            .find_map(|segment| segment.name)
+            .map(|name| self.names[name as usize].clone())
+    }
+
+    /// Get generated position for an original position in `source`
+    ///
+    /// `source` is the resolved source path, i.e. joined with the map's
+    /// `sourceRoot` as returned by [`Self::get_original_position`]. Lines are
+    /// 1-based and columns 0-based. With `GreatestLowerBound` the first
+    /// segment of the closest original position at or before the needle is
+    /// used, with `LeastUpperBound` the first one at or after it.
+    pub fn get_generated_position(
+        &self,
+        source: &str,
+        line: u32,
+        column: u32,
+        bias: Bias,
+    ) -> Option<GeneratedPosition> {
+        let entries = &self.original[*self.source_indices.get(source)?];
+        let needle = (line.checked_sub(1)?, column);
+        let index = match bias {
+            Bias::GreatestLowerBound => {
+                let upper =
+                    entries.partition_point(|e| (e.original_line, e.original_column) <= needle);
+                let last = upper.checked_sub(1)?;
+                let key = (entries[last].original_line, entries[last].original_column);
+                entries[..last].partition_point(|e| (e.original_line, e.original_column) < key)
+            }
+            Bias::LeastUpperBound => {
+                entries.partition_point(|e| (e.original_line, e.original_column) < needle)
             }
+        };
+        let entry = entries.get(index)?;
 
-            let segments: Vec<&str> = line.split(',').collect();
-            let mut line_segments = Vec::new();
+        Some(GeneratedPosition {
+            line: entry.generated_line + 1,
+            column: entry.generated_column,
+        })
+    }
 
-            for segment in segments {
-                if segment.is_empty() {
-                    continue;
-                }
+    /// Parse VLQ mappings using sourcemap crate's VLQ decoder
+    ///
+    /// Only the top-level `mappings` are parsed; sections of an index map
+    /// are not included.
+    pub fn parse_mappings(source_map: &SourceMap) -> Result<Vec<Vec<MappingSegment>>> {
+        let lines = decode_mappings(source_map)?;
+        let result = lines
+            .into_iter()
+            .enumerate()
+            .map(|(line_index, segments)| {
+                segments
+                    .into_iter()
+                    .map(|segment| MappingSegment {
+                        generated_line: line_index as u32,
+                        generated_column: segment.generated_column,
+                        source: segment
+                            .source
+                            .map(|source| source_map.sources[source as usize].clone()),
+                        original_line: segment.original_line,
+                        original_column: segment.original_column,
+                        name: segment
+                            .name
+                            .map(|name| source_map.names[name as usize].clone()),
+                    })
+                    .collect()
+            })
+            .collect();
 
-                // Use sourcemap crate's parse_vlq_segment function
-                let decoded_values =
-                    parse_vlq_segment(segment).map_err(|e| format!("VLQ decode error: {e:?}"))?;
+        Ok(result)
+    }
+}
 
-                if decoded_values.is_empty() {
-                    continue;
-                }
+/// Decode the VLQ `mappings` string into segments per generated line
+fn decode_mappings(source_map: &SourceMap) -> Result<Vec<Vec<Segment>>> {
+    let lines: Vec<&str> = source_map.mappings.split(';').collect();
+    let mut result = Vec::with_capacity(lines.len());
 
-                // Update generated column
-                generated_column += decoded_values[0];
+    // State variables for VLQ decoding
+    let mut generated_column = 0i64;
+    let mut source_index = 0i64;
+    let mut original_line = 0i64;
+    let mut original_column = 0i64;
+    let mut name_index = 0i64;
 
-                let mut mapping_segment = MappingSegment {
-                    generated_line: line_index as u32,
-                    generated_column: generated_column as u32,
-                    source: None,
-                    original_line: 0,
-                    original_column: 0,
-                    name: None,
-                };
+    for (line_index, line) in lines.iter().enumerate() {
+        if line_index > 0 {
+            generated_column = 0; // Reset for each line
+        }
 
-                // If there's source information (at least 4 values)
-                if decoded_values.len() >= 4 {
-                    source_index += decoded_values[1];
-                    original_line += decoded_values[2];
-                    original_column += decoded_values[3];
+        let mut line_segments = Vec::new();
 
-                    if source_index >= 0 && (source_index as usize) < self.source_map.sources.len()
-                    {
-                        mapping_segment.source =
-                            Some(self.source_map.sources[source_index as usize].clone());
-                    }
+        for (segment_index, segment) in line.split(',').enumerate() {
+            if segment.is_empty() {
+                continue;
+            }
 
-                    mapping_segment.original_line = original_line as u32;
-                    mapping_segment.original_column = original_column as u32;
+            // Use sourcemap crate's parse_vlq_segment function
+            let decoded_values = parse_vlq_segment(segment).map_err(|_| Error::Vlq {
+                line: line_index,
+                index: segment_index,
+                segment: segment.to_string(),
+            })?;
 
-                    // If there's a name index (5th value)
-                    if decoded_values.len() >= 5 {
-                        name_index += decoded_values[4];
-                        if name_index >= 0 && (name_index as usize) < self.source_map.names.len() {
-                            mapping_segment.name =
-                                Some(self.source_map.names[name_index as usize].clone());
-                        }
-                    }
+            if decoded_values.is_empty() {
+                continue;
+            }
+
+            // Accumulated values must stay valid positions and indices;
+            // u32::MAX is excluded as it marks the end of a line
+            let position = |value: i64| {
+                u32::try_from(value)
+                    .ok()
+                    .filter(|&value| value != u32::MAX)
+                    .ok_or_else(|| Error::Vlq {
+                        line: line_index,
+                        index: segment_index,
+                        segment: segment.to_string(),
+                    })
+            };
+
+            // Update generated column
+            generated_column += decoded_values[0];
+
+            let mut mapping_segment = Segment {
+                generated_column: position(generated_column)?,
+                source: None,
+                original_line: 0,
+                original_column: 0,
+                name: None,
+            };
+
+            // If there's source information (at least 4 values)
+            if decoded_values.len() >= 4 {
+                source_index += decoded_values[1];
+                original_line += decoded_values[2];
+                original_column += decoded_values[3];
+
+                let source = position(source_index)?;
+                if (source as usize) < source_map.sources.len() {
+                    mapping_segment.source = Some(source);
                 }
 
-                line_segments.push(mapping_segment);
+                mapping_segment.original_line = position(original_line)?;
+                mapping_segment.original_column = position(original_column)?;
+
+                // If there's a name index (5th value)
+                if decoded_values.len() >= 5 {
+                    name_index += decoded_values[4];
+                    if name_index >= 0 && (name_index as usize) < source_map.names.len() {
+                        mapping_segment.name = Some(name_index as u32);
+                    }
+                }
             }
 
-            result.push(line_segments);
+            line_segments.push(mapping_segment);
         }
 
-        Ok(result)
+        result.push(line_segments);
     }
+
+    Ok(result)
 }
 
 /// Original position in source code
@@ -116,6 +414,13 @@ pub struct OriginalPosition {
     pub name: Option<String>,
 }
 
+/// Generated position in transformed code
+#[derive(Debug, Clone)]
+pub struct GeneratedPosition {
+    pub line: u32,
+    pub column: u32,
+}
+
 /// Mapping segment from source map
 #[derive(Debug, Clone)]
 pub struct MappingSegment {
@@ -127,35 +432,138 @@ pub struct MappingSegment {
     pub name: Option<String>,
 }
 
+/// Find the URL of the trailing `//# sourceMappingURL=` comment in `code`
+///
+/// The legacy `//@` form is accepted as well. Query strings and fragments
+/// are removed from the URL.
+pub fn find_source_mapping_url(code: &str) -> Option<&str> {
+    code.lines().rev().find_map(|line| {
+        let comment = line.trim();
+        let url = comment
+            .strip_prefix("//# sourceMappingURL=")
+            .or_else(|| comment.strip_prefix("//@ sourceMappingURL="))?
+            .trim();
+        if url.starts_with("data:") {
+            return Some(url);
+        }
+        url.split(['?', '#']).next().filter(|url| !url.is_empty())
+    })
+}
+
+/// Decode a source map embedded in a `data:` URL
+///
+/// Both `;base64,` payloads and percent-encoded payloads (e.g.
+/// `data:application/json;charset=utf-8,%7B...`) are accepted.
+pub fn decode_data_url(url: &str) -> Result<SourceMap> {
+    let (header, payload) = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(','))
+        .ok_or_else(|| Error::InvalidDataUrl("missing `data:` header".to_string()))?;
+
+    let json = if header.ends_with(";base64") {
+        let payload: Vec<u8> = payload
+            .bytes()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect();
+        let encoding = if payload.len().is_multiple_of(4) {
+            &data_encoding::BASE64
+        } else {
+            &data_encoding::BASE64_NOPAD
+        };
+        encoding
+            .decode(&payload)
+            .map_err(|e| Error::InvalidDataUrl(e.to_string()))?
+    } else {
+        percent_decode(payload)
+    };
+
+    Ok(serde_json::from_slice(&json)?)
+}
+
+/// Decode `%XX` escapes, leaving malformed escapes as they are
+fn percent_decode(input: &str) -> Vec<u8> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
 /// Get mapping from source map for a generated location
 pub fn get_mapping(
-    source_map: &SourceMap,
+    decoder: &SourceMapDecoder,
     generated_location: &Location,
-    _orig_file: &str,
+    orig_file: &str,
 ) -> Option<Mapping> {
-    if source_map.sources.is_empty() {
-        return None;
-    }
-
-    let decoder = SourceMapDecoder::new(source_map.clone());
+    get_mapping_with_source(decoder, generated_location, orig_file)
+        .ok()
+        .map(|(mapping, _)| mapping)
+}
 
+/// Like [`get_mapping`], also returning the decoder's index of the source,
+/// or why the location could not be mapped
+pub(crate) fn get_mapping_with_source(
+    decoder: &SourceMapDecoder,
+    generated_location: &Location,
+    orig_file: &str,
+) -> Result<(Mapping, usize), DropReason> {
     // Get mapping for start position
-    let start_pos = decoder.get_original_position(
+    let start_pos = original_position_try_both(
+        decoder,
         generated_location.start.line,
         generated_location.start.column,
-    )?;
+    )
+    .ok_or(DropReason::Unmappable)?;
 
     // Get mapping for end position
-    let end_pos = decoder
-        .get_original_position(generated_location.end.line, generated_location.end.column)?;
+    let mut end_pos =
+        original_end_position(decoder, &generated_location.end).ok_or(DropReason::Unmappable)?;
 
     // Ensure both positions map to the same source
     if start_pos.source != end_pos.source {
-        return None;
+        return Err(DropReason::MultipleSources);
     }
 
-    Some(Mapping {
-        source: relative_to(&start_pos.source, _orig_file),
+    // An empty original range means the end fell inside the start segment,
+    // so use the column just before the next segment instead
+    if start_pos.line == end_pos.line && start_pos.column == end_pos.column {
+        let next = decoder
+            .get_original_position(
+                generated_location.end.line,
+                generated_location.end.column,
+                Bias::LeastUpperBound,
+            )
+            .ok_or(DropReason::Unmappable)?;
+        end_pos = OriginalPosition {
+            column: next.column.saturating_sub(1),
+            ..next
+        };
+    }
+
+    let source_index = decoder.source_indices[&start_pos.source];
+    let mapping = Mapping {
+        ignored: decoder.ignored[source_index],
+        source: resolve_mapped_source(&start_pos.source, orig_file),
+        name: decoder.name_at(
+            generated_location.start.line,
+            generated_location.start.column,
+        ),
         loc: Location {
             start: Position {
                 line: start_pos.line,
@@ -165,11 +573,84 @@ pub fn get_mapping(
                 line: end_pos.line,
                 column: end_pos.column,
             },
+            skip: false,
         },
-    })
+    };
+    Ok((mapping, source_index))
+}
+
+/// Look up a position with `GreatestLowerBound`, falling back to
+/// `LeastUpperBound` when nothing precedes the column on that line
+fn original_position_try_both(
+    decoder: &SourceMapDecoder,
+    line: u32,
+    column: u32,
+) -> Option<OriginalPosition> {
+    decoder
+        .get_original_position(line, column, Bias::GreatestLowerBound)
+        .or_else(|| decoder.get_original_position(line, column, Bias::LeastUpperBound))
 }
 
-/// Calculate relative path (simplified)
-fn relative_to(source: &str, _orig_file: &str) -> String {
-    source.to_string()
+/// Find the original end of a generated range ending at `generated_end`
+///
+/// Mirrors istanbul-lib-source-maps: the segment covering the last generated
+/// character (`column - 1`) gives the original start of that segment, and the
+/// range is extended up to the next original segment on the same line, or to
+/// the end of the line when there is none. A generated end at
+/// [`Position::END_OF_LINE`] maps to the end of the original line.
+fn original_end_position(
+    decoder: &SourceMapDecoder,
+    generated_end: &Position,
+) -> Option<OriginalPosition> {
+    let before_end = match generated_end.column.checked_sub(1) {
+        Some(column) => original_position_try_both(decoder, generated_end.line, column)?,
+        None => decoder.get_original_position(generated_end.line, 0, Bias::LeastUpperBound)?,
+    };
+    if generated_end.column == Position::END_OF_LINE {
+        return Some(OriginalPosition {
+            column: Position::END_OF_LINE,
+            ..before_end
+        });
+    }
+
+    let after_end = decoder
+        .get_generated_position(
+            &before_end.source,
+            before_end.line,
+            before_end.column.saturating_add(1),
+            Bias::LeastUpperBound,
+        )
+        .and_then(|generated| {
+            decoder.get_original_position(
+                generated.line,
+                generated.column,
+                Bias::GreatestLowerBound,
+            )
+        });
+
+    match after_end {
+        Some(after_end) if after_end.line == before_end.line => Some(after_end),
+        _ => Some(OriginalPosition {
+            column: Position::END_OF_LINE,
+            ..before_end
+        }),
+    }
+}
+
+/// Path reported for `source` of a map applied to the generated `orig_file`
+///
+/// This is the `source` of every [`Mapping`] returned by [`get_mapping`].
+pub fn resolve_mapped_source(source: &str, orig_file: &str) -> String {
+    pathutils::url_to_path(&relative_to(source, orig_file))
+}
+
+/// Resolve a mapped source against the directory of the generated file
+///
+/// Absolute paths and URLs are kept as they are; relative sources are joined
+/// onto the directory of `orig_file` with `.` and `..` segments normalized.
+fn relative_to(source: &str, orig_file: &str) -> String {
+    if pathutils::is_absolute(source) || pathutils::is_url(source) {
+        return source.to_string();
+    }
+    pathutils::join(pathutils::dirname(orig_file), source)
 }
@@ -1,15 +1,81 @@
 use crate::{
-    sourcemap::get_mapping, BranchMeta, CoverageMap, FileCoverage, FunctionMeta, Location,
+    pathutils,
+    report::{DiagnosticKind, DropReason, EntryKind, FileReport, TransformReport},
+    sorted_by_index,
+    sourcemap::{
+        decode_data_url, find_source_mapping_url, get_mapping_with_source, resolve_mapped_source,
+        SourceMapDecoder,
+    },
+    BranchMeta, CoverageMap, Error, Extra, FileCoverage, FunctionMeta, Location, Mapping, Result,
     SourceMap,
 };
-use anyhow::Result;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::fs;
+
+/// Maximum number of intermediate maps followed for a single location
+const MAX_CHAIN_DEPTH: usize = 32;
+
+/// Which name to keep for mapped functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FunctionNames {
+    /// Use the source map's original name when a named segment starts
+    /// exactly at the declaration, otherwise the generated name
+    #[default]
+    Original,
+    /// Always keep the name from the generated coverage
+    Generated,
+}
+
+/// What to do with files of which nothing could be mapped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnmappedFilePolicy {
+    /// Leave the file out of the result
+    #[default]
+    Drop,
+    /// Keep the generated file's coverage under its own path
+    KeepOriginal,
+    /// Keep the generated file's coverage and set its `unmapped` marker
+    KeepWithMarker,
+}
+
+/// What to do with branches of which only some arms could be mapped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BranchArmPolicy {
+    /// Keep every arm with its hits; unmapped arms are placed at the
+    /// branch's mapped location
+    #[default]
+    KeepAll,
+    /// Drop the whole branch
+    DropBranch,
+}
 
 /// Source map store for managing transformations
 pub struct SourceMapStore {
-    #[allow(dead_code)]
+    /// Root directory of the build, see [`SourceMapStore::with_base_dir`]
     base_dir: Option<String>,
-    data: HashMap<String, SourceMap>,
+    /// Registered source maps keyed by normalized generated file path
+    data: HashMap<String, StoredMap>,
+    /// Look for `sourceMappingURL` comments in generated files on disk
+    discover_source_maps: bool,
+    /// Drop coverage that maps into sources in a map's ignore list
+    skip_ignored_sources: bool,
+    /// Carry `sourcesContent` into the mapped coverage
+    include_sources_content: bool,
+    function_names: FunctionNames,
+    branch_arms: BranchArmPolicy,
+    /// Fail instead of dropping coverage that cannot be mapped
+    strict: bool,
+    unmapped_files: UnmappedFilePolicy,
+}
+
+/// Source map registered for a generated file
+enum StoredMap {
+    Map(SourceMap),
+    /// Path of a source map file, read when the coverage is transformed
+    File(String),
+    /// Inline `data:` URL, decoded when the coverage is transformed
+    DataUrl(String),
 }
 
 impl SourceMapStore {
@@ -17,49 +83,448 @@ impl SourceMapStore {
         Self {
             base_dir: None,
             data: HashMap::new(),
+            discover_source_maps: false,
+            skip_ignored_sources: false,
+            include_sources_content: false,
+            function_names: FunctionNames::default(),
+            branch_arms: BranchArmPolicy::default(),
+            strict: false,
+            unmapped_files: UnmappedFilePolicy::default(),
         }
     }
 
+    /// Create a store that resolves paths against `base_dir`
+    ///
+    /// The base directory affects only files that are remapped:
+    /// - relative generated paths (`FileCoverage.path`) are resolved against it,
+    /// - relative map sources are therefore resolved against the generated
+    ///   file's directory inside it,
+    /// - output paths inside it are made relative to it.
+    ///
+    /// Files without a source map are passed through with their paths
+    /// unchanged.
     pub fn with_base_dir(base_dir: String) -> Self {
         Self {
             base_dir: Some(base_dir),
-            data: HashMap::new(),
+            ..Self::new()
         }
     }
 
+    /// Enable or disable source map discovery on disk
+    ///
+    /// When enabled, coverage without an `inputSourceMap` or registered map
+    /// reads the generated file at `FileCoverage.path` and loads the map
+    /// referenced by its trailing `//# sourceMappingURL=` comment, relative
+    /// to the generated file, or decodes it when it is a `data:` URL.
+    /// Generated files that are missing or have no such comment are skipped;
+    /// referenced maps that cannot be read or parsed are reported as
+    /// [`DiagnosticKind::InvalidSourceMap`] unless strict mode is enabled.
+    pub fn with_source_map_discovery(mut self, enabled: bool) -> Self {
+        self.discover_source_maps = enabled;
+        self
+    }
+
+    /// Enable or disable skipping of ignored sources
+    ///
+    /// When enabled, statements, functions and branches that map into a
+    /// source listed in the map's `ignoreList` (or `x_google_ignoreList`),
+    /// such as bundler runtime code or `node_modules`, are dropped.
+    pub fn with_ignore_list(mut self, enabled: bool) -> Self {
+        self.skip_ignored_sources = enabled;
+        self
+    }
+
+    /// Enable or disable carrying over of original source text
+    ///
+    /// When enabled, each mapped file gets the `sourcesContent` entry of its
+    /// source as `FileCoverage.source_content` (`sourceContent` in JSON), so
+    /// reporters such as HTML reports can render files that are not on disk.
+    pub fn with_sources_content(mut self, enabled: bool) -> Self {
+        self.include_sources_content = enabled;
+        self
+    }
+
+    /// Choose between original and generated function names
+    ///
+    /// Minified code names functions `a`, `t` or `(unknown_3)`; by default
+    /// the name the source map records for the declaration is used instead.
+    pub fn with_function_names(mut self, function_names: FunctionNames) -> Self {
+        self.function_names = function_names;
+        self
+    }
+
+    /// Choose how branches with unmappable arms are handled
+    ///
+    /// Branch hits are positional, so an arm that cannot be mapped is
+    /// either kept at a placeholder location or the whole branch is
+    /// dropped; arms are never removed on their own.
+    pub fn with_branch_arms(mut self, policy: BranchArmPolicy) -> Self {
+        self.branch_arms = policy;
+        self
+    }
+
+    /// Enable or disable strict mode
+    ///
+    /// In strict mode `transform_coverage` fails with
+    /// [`Error::UnmappedEntry`] when a statement, function, branch or branch
+    /// arm cannot be mapped, with [`Error::UnmappableFile`] when a file has
+    /// coverage entries but none of them can be mapped, and with the loading
+    /// or decoding error of an invalid source map, instead of dropping the
+    /// coverage. Coverage dropped by
+    /// [`with_ignore_list`](Self::with_ignore_list) does not fail.
+    pub fn with_strict(mut self, enabled: bool) -> Self {
+        self.strict = enabled;
+        self
+    }
+
+    /// Choose what happens to files of which nothing could be mapped
+    ///
+    /// By default they are dropped from the result; they can be kept with
+    /// their generated coverage instead so that totals do not silently
+    /// improve. Either way a [`DiagnosticKind::FileIgnored`] diagnostic is
+    /// reported.
+    pub fn with_unmapped_files(mut self, policy: UnmappedFilePolicy) -> Self {
+        self.unmapped_files = policy;
+        self
+    }
+
+    /// Register the source map of a generated file
+    ///
+    /// It is used for coverage of `generated_path` that carries no
+    /// `inputSourceMap`.
+    pub fn register_map(&mut self, generated_path: &str, source_map: SourceMap) {
+        let key = self.generated_key(generated_path);
+        self.data.insert(key, StoredMap::Map(source_map));
+    }
+
+    /// Register the source map URL of a generated file
+    ///
+    /// A relative URL is resolved against the directory of `generated_path`.
+    /// The map file is read, or an inline `data:` URL decoded, when the
+    /// coverage is transformed. Remote URLs are not fetched; like maps that
+    /// cannot be read or parsed, they are reported as
+    /// [`DiagnosticKind::InvalidSourceMap`], or fail with
+    /// [`Error::PathResolution`] in strict mode.
+    pub fn register_url(&mut self, generated_path: &str, source_map_url: &str) {
+        let key = self.generated_key(generated_path);
+        let stored = if source_map_url.starts_with("data:") {
+            StoredMap::DataUrl(source_map_url.to_string())
+        } else if source_map_url.starts_with("file://") {
+            StoredMap::File(pathutils::url_to_path(source_map_url))
+        } else {
+            StoredMap::File(pathutils::join(pathutils::dirname(&key), source_map_url))
+        };
+        self.data.insert(key, stored);
+    }
+
+    /// Key of a generated file path in the registered maps
+    fn generated_key(&self, path: &str) -> String {
+        match &self.base_dir {
+            Some(base_dir) => pathutils::join(base_dir, path),
+            None => pathutils::normalize(path),
+        }
+    }
+
+    /// Look up the registered source map of a generated file
+    ///
+    /// `generated` is the file's key, see [`Self::generated_key`].
+    fn registered_map(&self, generated: &str) -> Result<Option<Cow<'_, SourceMap>>> {
+        match self.data.get(generated) {
+            Some(StoredMap::Map(source_map)) => Ok(Some(Cow::Borrowed(source_map))),
+            Some(StoredMap::File(file)) if pathutils::is_url(file) => Err(Error::PathResolution {
+                path: file.clone(),
+                reason: "remote source maps are not fetched".to_string(),
+            }),
+            Some(StoredMap::File(file)) => {
+                let content = fs::read_to_string(file).map_err(|source| Error::Io {
+                    path: file.clone(),
+                    source,
+                })?;
+                Ok(Some(Cow::Owned(parse_map_file(file, &content, generated)?)))
+            }
+            Some(StoredMap::DataUrl(url)) => Ok(Some(Cow::Owned(decode_data_url(url)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Load the source map referenced by the generated file on disk
+    ///
+    /// `generated` is the file's key, see [`Self::generated_key`].
+    fn discovered_map(&self, generated: &str) -> Result<Option<SourceMap>> {
+        let Ok(code) = fs::read_to_string(generated) else {
+            return Ok(None);
+        };
+        let Some(url) = find_source_mapping_url(&code) else {
+            return Ok(None);
+        };
+        if url.starts_with("data:") {
+            return decode_data_url(url).map(Some);
+        }
+
+        let file = pathutils::join(pathutils::dirname(generated), url);
+        let content = fs::read_to_string(&file).map_err(|source| Error::Io {
+            path: file.clone(),
+            source,
+        })?;
+        parse_map_file(&file, &content, generated).map(Some)
+    }
+
+    /// Find the source map for a file without an `inputSourceMap`
+    ///
+    /// `generated` is the file's key, see [`Self::generated_key`]; paths of
+    /// intermediate files are already resolved and used as they are.
+    fn source_map_for(&self, generated: &str) -> Result<Option<Cow<'_, SourceMap>>> {
+        if let Some(source_map) = self.registered_map(generated)? {
+            return Ok(Some(source_map));
+        }
+        if self.discover_source_maps {
+            return Ok(self.discovered_map(generated)?.map(Cow::Owned));
+        }
+        Ok(None)
+    }
+
+    /// Add the maps of intermediate files reachable from `decoder`
+    ///
+    /// Every source of the map that has a source map of its own (registered,
+    /// or discovered on disk when enabled) is an intermediate build stage;
+    /// its map is decoded and followed in turn. `visited` holds the paths
+    /// already looked up.
+    fn collect_intermediate_maps(
+        &self,
+        transformer: &mut SourceMapTransformer,
+        decoder: &SourceMapDecoder,
+        generated: &str,
+        visited: &mut HashSet<String>,
+        report: &mut TransformReport,
+    ) -> Result<()> {
+        let mut pending: Vec<String> = decoder
+            .sources()
+            .iter()
+            .map(|source| resolve_mapped_source(source, generated))
+            .collect();
+
+        // A map may list its own generated file as a source, e.g. after an
+        // in-place minify; that file is not an intermediate stage
+        let generated = pathutils::normalize(generated);
+        while let Some(path) = pending.pop() {
+            if path == generated || !visited.insert(path.clone()) {
+                continue;
+            }
+            let decoder = match self.source_map_for(&pathutils::normalize(&path)) {
+                Ok(None) => continue,
+                Ok(Some(source_map)) => SourceMapDecoder::new(&source_map),
+                Err(e) => Err(e),
+            };
+            let decoder = match decoder {
+                Ok(decoder) => decoder,
+                Err(e) if self.strict => return Err(e),
+                Err(e) => {
+                    report.warn(DiagnosticKind::InvalidSourceMap, &path, e.to_string());
+                    continue;
+                }
+            };
+            pending.extend(
+                decoder
+                    .sources()
+                    .iter()
+                    .map(|source| resolve_mapped_source(source, &path)),
+            );
+            transformer.add_intermediate_map(path, decoder);
+        }
+
+        Ok(())
+    }
+
     /// Transform coverage map using source maps
+    ///
+    /// Files without an `inputSourceMap` use the map registered for their
+    /// path, if any, or the one discovered on disk when enabled. Mapped
+    /// sources that have a map of their own are remapped through it as well,
+    /// so chains such as TS -> Babel -> bundle resolve to the authored file.
     pub fn transform_coverage(&self, coverage_map: CoverageMap) -> Result<CoverageMap> {
+        self.transform_coverage_with_report(coverage_map)
+            .map(|(coverage_map, _)| coverage_map)
+    }
+
+    /// Transform coverage map using source maps and report how it went
+    ///
+    /// Like [`transform_coverage`](Self::transform_coverage), but also
+    /// returns mapping statistics of every file that had a source map and
+    /// the diagnostics raised on the way, such as files of which nothing
+    /// could be mapped.
+    pub fn transform_coverage_with_report(
+        &self,
+        coverage_map: CoverageMap,
+    ) -> Result<(CoverageMap, TransformReport)> {
+        let mut report = TransformReport::default();
         let has_input_source_maps = coverage_map
             .values()
             .any(|fc| fc.input_source_map.is_some());
 
-        if !has_input_source_maps && self.data.is_empty() {
-            return Ok(coverage_map);
+        if !has_input_source_maps && self.data.is_empty() && !self.discover_source_maps {
+            return Ok((coverage_map, report));
         }
 
-        let transformer = SourceMapTransformer::new();
+        let mut transformer = match &self.base_dir {
+            Some(base_dir) => SourceMapTransformer::with_base_dir(base_dir.clone()),
+            None => SourceMapTransformer::new(),
+        }
+        .with_ignore_list(self.skip_ignored_sources)
+        .with_sources_content(self.include_sources_content)
+        .with_function_names(self.function_names)
+        .with_branch_arms(self.branch_arms);
         let mut unique_files: HashMap<String, MappedCoverage> = HashMap::new();
+        let mut visited = HashSet::new();
 
         // 使用不同的方法来避免生命周期问题
 
         for (file_path, fc) in coverage_map {
-            if let Some(source_map) = &fc.input_source_map {
-                let changed = transformer.process_file(&fc, source_map, &mut unique_files);
-                if !changed {
-                    println!("File [{file_path}] ignored, nothing could be mapped");
+            let source_map = match &fc.input_source_map {
+                Some(source_map) => Ok(Some(Cow::Borrowed(source_map))),
+                None => self.source_map_for(&self.generated_key(&fc.path)),
+            };
+            let decoder = match source_map {
+                Ok(None) => {
+                    let key = get_unique_key(&file_path);
+                    unique_files.insert(key, MappedCoverage::from_file_coverage(fc));
+                    continue;
                 }
-            } else {
-                let key = get_unique_key(&file_path);
-                unique_files.insert(key, MappedCoverage::from_file_coverage(fc));
+                Ok(Some(source_map)) => SourceMapDecoder::new(&source_map),
+                Err(e) => Err(e),
+            };
+            let file_report = match decoder {
+                Ok(decoder) => {
+                    let generated = transformer.resolve_generated_path(&fc.path);
+                    self.collect_intermediate_maps(
+                        &mut transformer,
+                        &decoder,
+                        &generated,
+                        &mut visited,
+                        &mut report,
+                    )?;
+                    transformer.process_decoded_file_with_report(&fc, &decoder, &mut unique_files)
+                }
+                Err(e) if self.strict => return Err(e),
+                Err(e) => {
+                    report.warn(DiagnosticKind::InvalidSourceMap, &file_path, e.to_string());
+                    FileReport::unmapped(&fc)
+                }
+            };
+            if self.strict {
+                check_strict(&file_path, &file_report)?;
+            }
+            let unmapped = file_report.is_unmappable();
+            report.files.insert(file_path.clone(), file_report);
+            if !unmapped {
+                continue;
+            }
+
+            if self.unmapped_files == UnmappedFilePolicy::Drop {
+                report.warn(
+                    DiagnosticKind::FileIgnored,
+                    &file_path,
+                    format!("File [{file_path}] ignored, nothing could be mapped"),
+                );
+                continue;
             }
+            report.warn(
+                DiagnosticKind::FileIgnored,
+                &file_path,
+                format!("File [{file_path}] kept unmapped, nothing could be mapped"),
+            );
+            let mut fc = fc;
+            fc.unmapped = self.unmapped_files == UnmappedFilePolicy::KeepWithMarker;
+            unique_files.insert(
+                get_unique_key(&file_path),
+                MappedCoverage::from_file_coverage(fc),
+            );
         }
 
-        let mut result = HashMap::new();
-        for (_, mc) in unique_files {
+        let mut result = CoverageMap::new();
+        for (_, mut mc) in unique_files {
+            // Passed-through files have nothing added and keep their indices
+            if !mc.meta.seen.is_empty() {
+                mc.sort_by_location();
+            }
             result.insert(mc.file_coverage.path.clone(), mc.file_coverage);
         }
 
-        Ok(result)
+        Ok((result, report))
+    }
+}
+
+/// Fail on the first coverage entry or branch arm of a file that was not
+/// mapped
+///
+/// Entries dropped because their source is ignore-listed were dropped on
+/// purpose and never fail.
+fn check_strict(file_path: &str, file_report: &FileReport) -> Result<()> {
+    if file_report.is_unmappable() {
+        return Err(Error::UnmappableFile {
+            path: file_path.to_string(),
+        });
+    }
+    let entry = file_report
+        .dropped
+        .iter()
+        .find(|entry| entry.reason != DropReason::Ignored);
+    if let Some(entry) = entry {
+        return Err(Error::UnmappedEntry {
+            path: file_path.to_string(),
+            kind: entry.kind,
+            id: entry.id.clone(),
+            line: entry.loc.start.line,
+            column: entry.loc.start.column,
+            reason: entry.reason,
+        });
+    }
+    let arm = file_report
+        .placeholder_arms
+        .iter()
+        .find(|arm| arm.reason != DropReason::Ignored);
+    match arm {
+        Some(arm) => Err(Error::UnmappedEntry {
+            path: file_path.to_string(),
+            kind: EntryKind::Branch,
+            id: arm.id.clone(),
+            line: arm.loc.start.line,
+            column: arm.loc.start.column,
+            reason: arm.reason,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Parse the content of a source map file
+///
+/// Sources of a map file are relative to the map itself, while mapped
+/// sources are resolved against the generated file's directory, so a
+/// relative `sourceRoot` is rebased when the two directories differ.
+fn parse_map_file(file: &str, content: &str, generated: &str) -> Result<SourceMap> {
+    let mut source_map: SourceMap =
+        serde_json::from_str(content).map_err(|source| Error::InvalidSourceMap {
+            path: file.to_string(),
+            source,
+        })?;
+
+    let map_dir = pathutils::relative(pathutils::dirname(generated), pathutils::dirname(file));
+    if map_dir != "." {
+        rebase_source_root(&mut source_map, &map_dir);
+    }
+    Ok(source_map)
+}
+
+/// Prefix a relative `sourceRoot` with `map_dir`
+///
+/// Sections of an index map carry their own `sourceRoot`, so they are
+/// rebased as well.
+fn rebase_source_root(source_map: &mut SourceMap, map_dir: &str) {
+    let source_root = source_map.source_root.as_deref().unwrap_or_default();
+    if !pathutils::is_absolute(source_root) && !pathutils::is_url(source_root) {
+        source_map.source_root = Some(pathutils::join(map_dir, source_root));
+    }
+    for section in source_map.sections.iter_mut().flatten() {
+        rebase_source_root(&mut section.map, map_dir);
     }
 }
 
@@ -70,114 +535,327 @@ impl Default for SourceMapStore {
 }
 
 /// Source map transformer
-pub struct SourceMapTransformer;
+pub struct SourceMapTransformer {
+    base_dir: Option<String>,
+    skip_ignored_sources: bool,
+    include_sources_content: bool,
+    function_names: FunctionNames,
+    branch_arms: BranchArmPolicy,
+    /// Maps of intermediate build outputs keyed by their mapped path
+    intermediate: HashMap<String, SourceMapDecoder>,
+}
 
 impl SourceMapTransformer {
     pub fn new() -> Self {
-        Self
+        Self {
+            base_dir: None,
+            skip_ignored_sources: false,
+            include_sources_content: false,
+            function_names: FunctionNames::default(),
+            branch_arms: BranchArmPolicy::default(),
+            intermediate: HashMap::new(),
+        }
+    }
+
+    /// Create a transformer that resolves paths against `base_dir`, see
+    /// [`SourceMapStore::with_base_dir`]
+    pub fn with_base_dir(base_dir: String) -> Self {
+        Self {
+            base_dir: Some(base_dir),
+            ..Self::new()
+        }
+    }
+
+    /// Drop locations that map into ignored sources, see
+    /// [`SourceMapStore::with_ignore_list`]
+    pub fn with_ignore_list(mut self, enabled: bool) -> Self {
+        self.skip_ignored_sources = enabled;
+        self
+    }
+
+    /// Carry `sourcesContent` into the output, see
+    /// [`SourceMapStore::with_sources_content`]
+    pub fn with_sources_content(mut self, enabled: bool) -> Self {
+        self.include_sources_content = enabled;
+        self
+    }
+
+    /// Choose between original and generated function names, see
+    /// [`SourceMapStore::with_function_names`]
+    pub fn with_function_names(mut self, function_names: FunctionNames) -> Self {
+        self.function_names = function_names;
+        self
+    }
+
+    /// Choose how branches with unmappable arms are handled, see
+    /// [`SourceMapStore::with_branch_arms`]
+    pub fn with_branch_arms(mut self, policy: BranchArmPolicy) -> Self {
+        self.branch_arms = policy;
+        self
+    }
+
+    /// Add the map of an intermediate build output
+    ///
+    /// Locations that map into `path` (as reported by
+    /// [`resolve_mapped_source`], before base directory relativization) are
+    /// mapped again through `decoder`.
+    pub fn add_intermediate_map(&mut self, path: String, decoder: SourceMapDecoder) {
+        self.intermediate.insert(path, decoder);
+    }
+
+    /// Resolve a relative generated file path against the base directory
+    fn resolve_generated_path(&self, path: &str) -> String {
+        match &self.base_dir {
+            Some(base_dir) if !pathutils::is_absolute(path) => pathutils::join(base_dir, path),
+            _ => path.to_string(),
+        }
+    }
+
+    /// Map a generated location through the map chain and make its source
+    /// relative to the base directory
+    fn map_location<'a>(
+        &'a self,
+        decoder: &'a SourceMapDecoder,
+        loc: &Location,
+        orig_file: &str,
+    ) -> Result<MappedLocation<'a>, DropReason> {
+        let (mut mapping, mut source_index) = get_mapping_with_source(decoder, loc, orig_file)?;
+        let mut decoder = decoder;
+        let mut ignored = mapping.ignored;
+        // Files whose map was applied, so that a cycle never reapplies one
+        let mut applied = vec![pathutils::normalize(orig_file)];
+        for _ in 0..MAX_CHAIN_DEPTH {
+            let Some(next) = self.intermediate.get(&mapping.source) else {
+                break;
+            };
+            if applied.contains(&mapping.source) {
+                break;
+            }
+            applied.push(mapping.source.clone());
+            let name = mapping.name.take();
+            (mapping, source_index) = get_mapping_with_source(next, &mapping.loc, &mapping.source)?;
+            decoder = next;
+            ignored |= mapping.ignored;
+            mapping.name = mapping.name.or(name);
+        }
+        if ignored && self.skip_ignored_sources {
+            return Err(DropReason::Ignored);
+        }
+        if let Some(base_dir) = &self.base_dir {
+            mapping.source = pathutils::relative_to_base(base_dir, &mapping.source);
+        }
+        mapping.loc.skip = loc.skip;
+
+        let content = if self.include_sources_content {
+            decoder.source_content_at(source_index)
+        } else {
+            None
+        };
+        Ok(MappedLocation { mapping, content })
     }
 
     /// Process a single file's coverage data
+    ///
+    /// The source map is decoded once and reused for every location in `fc`.
     pub fn process_file(
         &self,
         fc: &FileCoverage,
         source_map: &SourceMap,
         unique_files: &mut HashMap<String, MappedCoverage>,
     ) -> bool {
-        let mut changes = 0;
+        match SourceMapDecoder::new(source_map) {
+            Ok(decoder) => self.process_decoded_file(fc, &decoder, unique_files),
+            Err(_) => false,
+        }
+    }
+
+    /// Process a single file's coverage data with an already decoded map
+    pub fn process_decoded_file(
+        &self,
+        fc: &FileCoverage,
+        decoder: &SourceMapDecoder,
+        unique_files: &mut HashMap<String, MappedCoverage>,
+    ) -> bool {
+        self.process_decoded_file_with_report(fc, decoder, unique_files)
+            .mapped()
+            > 0
+    }
+
+    /// Process a single file's coverage data and count what was mapped
+    pub fn process_decoded_file_with_report(
+        &self,
+        fc: &FileCoverage,
+        decoder: &SourceMapDecoder,
+        unique_files: &mut HashMap<String, MappedCoverage>,
+    ) -> FileReport {
+        let orig_file = self.resolve_generated_path(&fc.path);
+        let mut report = FileReport::default();
 
         // Process statements
-        for (s, loc) in &fc.statement_map {
+        for (s, loc) in sorted_by_index(&fc.statement_map) {
             let hits = fc.s.get(s).copied().unwrap_or(0);
-            if let Some(mapping) = get_mapping(source_map, loc, &fc.path) {
-                changes += 1;
-                let key = get_unique_key(&mapping.source);
-                if !unique_files.contains_key(&key) {
-                    unique_files.insert(key.clone(), MappedCoverage::new(mapping.source.clone()));
+            match self.map_location(decoder, loc, &orig_file) {
+                Ok(mapped) => {
+                    let mc = mapped_coverage(unique_files, &mapped.mapping.source, mapped.content);
+                    let next = mc.meta.last.s;
+                    let merged = mc.add_statement(mapped.mapping.loc, hits) < next;
+                    report.statements.record_mapped(merged);
                 }
-                unique_files
-                    .get_mut(&key)
-                    .unwrap()
-                    .add_statement(mapping.loc, hits);
+                Err(reason) => report.record_dropped(EntryKind::Statement, s, loc, reason),
             }
         }
 
         // Process functions
-        for (f, fn_meta) in &fc.fn_map {
+        for (f, fn_meta) in sorted_by_index(&fc.fn_map) {
             let hits = fc.f.get(f).copied().unwrap_or(0);
-            let mapping = get_mapping(source_map, &fn_meta.decl, &fc.path);
-            let span_mapping = get_mapping(source_map, &fn_meta.loc, &fc.path);
-
-            if let (Some(mapping), Some(span_mapping)) = (mapping, span_mapping) {
-                if mapping.source == span_mapping.source {
-                    changes += 1;
-                    let key = get_unique_key(&mapping.source);
-                    if !unique_files.contains_key(&key) {
-                        unique_files
-                            .insert(key.clone(), MappedCoverage::new(mapping.source.clone()));
+            let mapped = self.map_location(decoder, &fn_meta.decl, &orig_file);
+            let span_mapped = self.map_location(decoder, &fn_meta.loc, &orig_file);
+
+            match (mapped, span_mapped) {
+                (Ok(mapped), Ok(span_mapped))
+                    if mapped.mapping.source == span_mapped.mapping.source =>
+                {
+                    let name = match (self.function_names, mapped.mapping.name) {
+                        (FunctionNames::Original, Some(name)) => name,
+                        _ => fn_meta.name.clone(),
+                    };
+                    let mc = mapped_coverage(unique_files, &mapped.mapping.source, mapped.content);
+                    let next = mc.meta.last.f;
+                    let index =
+                        mc.add_function(name, mapped.mapping.loc, span_mapped.mapping.loc, hits);
+                    let merged = index < next;
+                    // Like statements, a merged function keeps the first one's flag
+                    if !merged {
+                        let fn_map = &mut mc.file_coverage.fn_map;
+                        fn_map.get_mut(&index.to_string()).unwrap().skip = fn_meta.skip;
                     }
-                    unique_files.get_mut(&key).unwrap().add_function(
-                        fn_meta.name.clone(),
-                        mapping.loc,
-                        span_mapping.loc,
-                        hits,
-                    );
+                    report.functions.record_mapped(merged);
+                }
+                (Ok(_), Ok(_)) => report.record_dropped(
+                    EntryKind::Function,
+                    f,
+                    &fn_meta.loc,
+                    DropReason::MultipleSources,
+                ),
+                (Err(reason), _) | (_, Err(reason)) => {
+                    report.record_dropped(EntryKind::Function, f, &fn_meta.loc, reason)
                 }
             }
         }
 
         // Process branches
-        for (b, branch_meta) in &fc.branch_map {
-            let hits = fc.b.get(b).cloned().unwrap_or_default();
+        for (b, branch_meta) in sorted_by_index(&fc.branch_map) {
+            let mut hits = fc.b.get(b).cloned().unwrap_or_default();
+            hits.truncate(branch_meta.locations.len());
+            let truthy_hits = fc.b_t.as_ref().and_then(|b_t| b_t.get(b)).map(|hits| {
+                let mut hits = hits.clone();
+                hits.truncate(branch_meta.locations.len());
+                hits
+            });
             let mut locs = Vec::new();
-            let mut mapped_hits = Vec::new();
             let mut source = None;
-            let mut skip = false;
+            let mut content = None;
+            let mut multi_source = false;
+            let mut unmapped_arms = Vec::new();
 
-            for (i, loc) in branch_meta.locations.iter().enumerate() {
-                if let Some(mapping) = get_mapping(source_map, loc, &fc.path) {
-                    if source.is_none() {
-                        source = Some(mapping.source.clone());
-                    }
-                    if source.as_ref() != Some(&mapping.source) {
-                        skip = true;
-                    }
-                    locs.push(mapping.loc);
-                    if i < hits.len() {
-                        mapped_hits.push(hits[i]);
+            for (arm, loc) in branch_meta.locations.iter().enumerate() {
+                let mapped = match self.map_location(decoder, loc, &orig_file) {
+                    Ok(mapped) => mapped,
+                    Err(reason) => {
+                        unmapped_arms.push((arm, reason));
+                        locs.push(None);
+                        continue;
                     }
+                };
+                if source.is_none() {
+                    source = Some(mapped.mapping.source.clone());
+                    content = mapped.content;
                 }
+                if source.as_ref() != Some(&mapped.mapping.source) {
+                    multi_source = true;
+                }
+                locs.push(Some(mapped.mapping.loc));
             }
 
             let loc_mapping =
                 if branch_meta.loc.start.line != 0 || branch_meta.loc.start.column != 0 {
-                    get_mapping(source_map, &branch_meta.loc, &fc.path)
+                    self.map_location(decoder, &branch_meta.loc, &orig_file)
+                        .ok()
                 } else {
                     None
                 };
 
-            if !skip && !locs.is_empty() {
-                if let Some(source) = source {
-                    changes += 1;
-                    let key = get_unique_key(&source);
-                    if !unique_files.contains_key(&key) {
-                        unique_files.insert(key.clone(), MappedCoverage::new(source.clone()));
-                    }
+            let dropped = unmapped_arms.first().map(|&(_, reason)| reason);
+            match (source, dropped) {
+                (Some(_), _) if multi_source => report.record_dropped(
+                    EntryKind::Branch,
+                    b,
+                    &branch_meta.loc,
+                    DropReason::MultipleSources,
+                ),
+                (Some(_), Some(reason)) if self.branch_arms == BranchArmPolicy::DropBranch => {
+                    report.record_dropped(EntryKind::Branch, b, &branch_meta.loc, reason)
+                }
+                (Some(source), _) => {
                     let branch_loc = loc_mapping
-                        .map(|m| m.loc)
-                        .unwrap_or_else(|| locs[0].clone());
-                    unique_files.get_mut(&key).unwrap().add_branch(
-                        branch_meta.branch_type.clone(),
-                        branch_loc,
-                        locs,
-                        mapped_hits,
-                    );
+                        .map(|m| m.mapping.loc)
+                        .or_else(|| locs.iter().flatten().next().cloned())
+                        .expect("a branch with a source has a mapped arm");
+                    // Unmapped arms keep their hits at the branch location
+                    let locs = locs
+                        .into_iter()
+                        .map(|loc| loc.unwrap_or_else(|| branch_loc.clone()))
+                        .collect();
+                    let mc = mapped_coverage(unique_files, &source, content);
+                    let next = mc.meta.last.b;
+                    let index =
+                        mc.add_branch(branch_meta.branch_type.clone(), branch_loc, locs, hits);
+                    if let Some(truthy_hits) = truthy_hits {
+                        mc.add_branch_truthiness(index, truthy_hits);
+                    }
+                    let merged = index < next;
+                    if !merged {
+                        let branch_map = &mut mc.file_coverage.branch_map;
+                        branch_map.get_mut(&index.to_string()).unwrap().skip = branch_meta.skip;
+                    }
+                    report.branches.record_mapped(merged);
+                    for (arm, reason) in unmapped_arms {
+                        report.record_placeholder_arm(b, arm, &branch_meta.locations[arm], reason);
+                    }
                 }
+                (None, reason) => report.record_dropped(
+                    EntryKind::Branch,
+                    b,
+                    &branch_meta.loc,
+                    reason.unwrap_or(DropReason::Unmappable),
+                ),
             }
         }
 
-        changes > 0
+        report
+    }
+}
+
+/// Location mapped through the map chain
+struct MappedLocation<'a> {
+    mapping: Mapping,
+    /// Original source text, when sources content is carried over
+    content: Option<&'a str>,
+}
+
+/// Get or create the mapped coverage of `source`, filling in its content
+fn mapped_coverage<'a>(
+    unique_files: &'a mut HashMap<String, MappedCoverage>,
+    source: &str,
+    content: Option<&str>,
+) -> &'a mut MappedCoverage {
+    let mc = unique_files
+        .entry(get_unique_key(source))
+        .or_insert_with(|| MappedCoverage::new(source.to_string()));
+    if mc.file_coverage.source_content.is_none() {
+        mc.file_coverage.source_content = content.map(str::to_string);
     }
+    mc
 }
 
 impl Default for SourceMapTransformer {
@@ -214,7 +892,14 @@ impl MappedCoverage {
                 s: HashMap::new(),
                 f: HashMap::new(),
                 b: HashMap::new(),
+                b_t: None,
                 input_source_map: None,
+                source_content: None,
+                unmapped: false,
+                hash: None,
+                coverage_schema: None,
+                all: None,
+                extra: Extra::new(),
             },
             meta: MappedCoverageMeta {
                 last: LastIndices { s: 0, f: 0, b: 0 },
@@ -286,7 +971,10 @@ impl MappedCoverage {
                 FunctionMeta {
                     name: fn_name,
                     decl,
+                    line: Some(loc.start.line),
                     loc,
+                    skip: false,
+                    extra: Extra::new(),
                 },
             );
             self.file_coverage.f.insert(index_str, hits);
@@ -311,11 +999,7 @@ impl MappedCoverage {
         if let Some(&index) = self.meta.seen.get(&key) {
             let index_str = index.to_string();
             if let Some(existing_hits) = self.file_coverage.b.get_mut(&index_str) {
-                for (i, hit) in hits.iter().enumerate() {
-                    if i < existing_hits.len() {
-                        existing_hits[i] += hit;
-                    }
-                }
+                add_hits(existing_hits, &hits);
             }
             index
         } else {
@@ -328,17 +1012,109 @@ impl MappedCoverage {
                 index_str.clone(),
                 BranchMeta {
                     branch_type,
+                    line: Some(loc.start.line),
                     loc,
                     locations: branch_locations,
+                    skip: false,
+                    extra: Extra::new(),
                 },
             );
             self.file_coverage.b.insert(index_str, hits);
             index
         }
     }
+
+    /// Renumber statements, functions and branches by original location
+    ///
+    /// Indices are assigned as coverage is added, in the order of the
+    /// generated code; afterwards they follow the order of the source.
+    pub fn sort_by_location(&mut self) {
+        let fc = &mut self.file_coverage;
+        let statements = renumber(&mut fc.statement_map, loc_order);
+        let functions = renumber(&mut fc.fn_map, |f| (loc_order(&f.loc), loc_order(&f.decl)));
+        let branches = renumber(&mut fc.branch_map, |b| {
+            let locations: Vec<_> = b.locations.iter().map(loc_order).collect();
+            (loc_order(&b.loc), locations)
+        });
+
+        reindex(&mut fc.s, &statements);
+        reindex(&mut fc.f, &functions);
+        reindex(&mut fc.b, &branches);
+        if let Some(b_t) = &mut fc.b_t {
+            reindex(b_t, &branches);
+        }
+        for (key, index) in &mut self.meta.seen {
+            let order = match key.as_bytes()[0] {
+                b's' => &statements,
+                b'f' => &functions,
+                _ => &branches,
+            };
+            if let Some(&new_index) = order.get(&index.to_string()) {
+                *index = new_index;
+            }
+        }
+    }
+
+    /// Add the `bT` hits of the branch at `index` returned by
+    /// [`add_branch`](Self::add_branch), merging them like its `b` hits
+    pub fn add_branch_truthiness(&mut self, index: usize, hits: Vec<u32>) {
+        let b_t = self.file_coverage.b_t.get_or_insert_with(HashMap::new);
+        match b_t.entry(index.to_string()) {
+            Entry::Occupied(mut entry) => add_hits(entry.get_mut(), &hits),
+            Entry::Vacant(entry) => {
+                entry.insert(hits);
+            }
+        }
+    }
 }
 
 /// Helper functions
+fn add_hits(existing_hits: &mut [u32], hits: &[u32]) {
+    for (existing, hit) in existing_hits.iter_mut().zip(hits) {
+        *existing += hit;
+    }
+}
+
+/// Sort key of a location, by start then end
+fn loc_order(loc: &Location) -> (u32, u32, u32, u32) {
+    (
+        loc.start.line,
+        loc.start.column,
+        loc.end.line,
+        loc.end.column,
+    )
+}
+
+/// Renumber the entries of an index map in the order of `order_key`,
+/// returning the new index of every old key
+fn renumber<V, K: Ord>(
+    map: &mut HashMap<String, V>,
+    order_key: impl Fn(&V) -> K,
+) -> HashMap<String, usize> {
+    let mut entries: Vec<(String, V)> = map.drain().collect();
+    entries.sort_by_cached_key(|(key, value)| {
+        (order_key(value), key.parse::<u64>().unwrap_or(u64::MAX))
+    });
+
+    let mut indices = HashMap::new();
+    for (index, (key, value)) in entries.into_iter().enumerate() {
+        map.insert(index.to_string(), value);
+        indices.insert(key, index);
+    }
+    indices
+}
+
+/// Move the entries of a hit map to the indices given by [`renumber`]
+fn reindex<V>(map: &mut HashMap<String, V>, indices: &HashMap<String, usize>) {
+    *map = map
+        .drain()
+        .map(|(key, value)| match indices.get(&key) {
+            Some(index) => (index.to_string(), value),
+            None => (key, value),
+        })
+        .collect();
+}
+
 fn loc_string(loc: &Location) -> String {
     format!(
         "{}:{}:{}:{}",
@@ -79,6 +79,7 @@ fn test_mapped_coverage_add_statement() {
             line: 1,
             column: 10,
         },
+        skip: false,
     };
 
     let index = mc.add_statement(loc, 5);
@@ -93,6 +94,7 @@ fn test_mapped_coverage_add_statement() {
                 line: 1,
                 column: 10,
             },
+            skip: false,
         },
         3,
     );
@@ -110,10 +112,12 @@ fn test_mapped_coverage_add_function() {
             line: 1,
             column: 19,
         },
+        skip: false,
     };
     let fn_loc = Location {
         start: Position { line: 1, column: 0 },
         end: Position { line: 3, column: 1 },
+        skip: false,
     };
 
     let index = mc.add_function("testFn".to_string(), decl_loc, fn_loc, 3);
@@ -134,6 +138,7 @@ fn test_mapped_coverage_add_branch() {
             line: 2,
             column: 20,
         },
+        skip: false,
     };
     let branch_locs = vec![
         Location {
@@ -142,6 +147,7 @@ fn test_mapped_coverage_add_branch() {
                 line: 2,
                 column: 10,
             },
+            skip: false,
         },
         Location {
             start: Position {
@@ -152,6 +158,7 @@ fn test_mapped_coverage_add_branch() {
                 line: 2,
                 column: 20,
             },
+            skip: false,
         },
     ];
     let branch_hits = vec![1, 0];
@@ -192,6 +199,7 @@ fn test_location_string_formatting() {
             line: 2,
             column: 10,
         },
+        skip: false,
     };
 
     // 这个测试需要访问内部函数，我们通过间接方式测试
@@ -215,6 +223,7 @@ fn test_unique_key_generation() {
             line: 1,
             column: 10,
         },
+        skip: false,
     };
 
     mc1.add_statement(loc.clone(), 1);
@@ -224,3 +233,1685 @@ fn test_unique_key_generation() {
     assert_eq!(mc1.file_coverage.path, "src/app.js");
     assert_eq!(mc2.file_coverage.path, "src\\app.js");
 }
+
+#[test]
+fn test_decoder_original_position_lookup() {
+    let source_map: SourceMap = serde_json::from_str(
+        r#"{
+            "version": 3,
+            "sources": ["src/app.ts"],
+            "names": ["testFunction"],
+            "mappings": "AAAA,SAASA;AACT"
+        }"#,
+    )
+    .unwrap();
+    let decoder = SourceMapDecoder::new(&source_map).unwrap();
+
+    let pos = decoder
+        .get_original_position(1, 12, Bias::GreatestLowerBound)
+        .unwrap();
+    assert_eq!(pos.source, "src/app.ts");
+    assert_eq!((pos.line, pos.column), (1, 9));
+    assert_eq!(pos.name.as_deref(), Some("testFunction"));
+
+    let pos = decoder
+        .get_original_position(1, 3, Bias::GreatestLowerBound)
+        .unwrap();
+    assert_eq!((pos.line, pos.column), (1, 0));
+    assert_eq!(pos.name, None);
+
+    let pos = decoder
+        .get_original_position(2, 5, Bias::GreatestLowerBound)
+        .unwrap();
+    assert_eq!((pos.line, pos.column), (2, 0));
+
+    // 没有对应映射的行返回None
+    assert!(decoder
+        .get_original_position(3, 0, Bias::GreatestLowerBound)
+        .is_none());
+    assert!(decoder
+        .get_original_position(0, 0, Bias::GreatestLowerBound)
+        .is_none());
+}
+
+#[test]
+fn test_decoder_lookup_bias() {
+    let source_map: SourceMap = serde_json::from_str(
+        r#"{
+            "version": 3,
+            "sources": ["src/app.ts"],
+            "names": [],
+            "mappings": "IAAA,UAAU"
+        }"#,
+    )
+    .unwrap();
+    let decoder = SourceMapDecoder::new(&source_map).unwrap();
+
+    // 第一个段之前的列只能通过LeastUpperBound找到
+    assert!(decoder
+        .get_original_position(1, 2, Bias::GreatestLowerBound)
+        .is_none());
+    let pos = decoder
+        .get_original_position(1, 2, Bias::LeastUpperBound)
+        .unwrap();
+    assert_eq!((pos.line, pos.column), (1, 0));
+
+    let pos = decoder
+        .get_original_position(1, 6, Bias::LeastUpperBound)
+        .unwrap();
+    assert_eq!((pos.line, pos.column), (1, 10));
+    assert!(decoder
+        .get_original_position(1, 20, Bias::LeastUpperBound)
+        .is_none());
+}
+
+#[test]
+fn test_get_mapping_falls_back_to_least_upper_bound() {
+    let source_map: SourceMap = serde_json::from_str(
+        r#"{
+            "version": 3,
+            "sources": ["src/app.ts"],
+            "names": [],
+            "mappings": "IAAA,UAAU"
+        }"#,
+    )
+    .unwrap();
+    let decoder = SourceMapDecoder::new(&source_map).unwrap();
+    let loc = Location {
+        start: Position { line: 1, column: 0 },
+        end: Position {
+            line: 1,
+            column: 12,
+        },
+        skip: false,
+    };
+
+    let mapping = get_mapping(&decoder, &loc, "app.js").unwrap();
+    assert_eq!(mapping.source, "src/app.ts");
+    assert_eq!(mapping.loc.start, Position { line: 1, column: 0 });
+}
+
+#[test]
+fn test_get_mapping_end_extends_to_next_segment() {
+    let source_map: SourceMap = serde_json::from_str(
+        r#"{
+            "version": 3,
+            "sources": ["src/app.ts"],
+            "names": [],
+            "mappings": "AAAA,IAAI,IAAI,EAAE"
+        }"#,
+    )
+    .unwrap();
+    let decoder = SourceMapDecoder::new(&source_map).unwrap();
+    let loc = |start: u32, end: u32| Location {
+        start: Position {
+            line: 1,
+            column: start,
+        },
+        end: Position {
+            line: 1,
+            column: end,
+        },
+        skip: false,
+    };
+
+    // 结束位置取column - 1所在段，并延伸到下一个原始段
+    let mapping = get_mapping(&decoder, &loc(0, 9), "dist/app.js").unwrap();
+    assert_eq!(mapping.loc.start, Position { line: 1, column: 0 });
+    assert_eq!(
+        mapping.loc.end,
+        Position {
+            line: 1,
+            column: 10
+        }
+    );
+
+    // 同一行之后没有原始段时延伸到行尾
+    let mapping = get_mapping(&decoder, &loc(8, 12), "dist/app.js").unwrap();
+    assert_eq!(mapping.loc.start, Position { line: 1, column: 8 });
+    assert_eq!(mapping.loc.end.column, Position::END_OF_LINE);
+
+    let json = serde_json::to_string(&mapping.loc.end).unwrap();
+    assert_eq!(json, r#"{"line":1,"column":null}"#);
+    let end: Position = serde_json::from_str(&json).unwrap();
+    assert_eq!(end, mapping.loc.end);
+}
+
+#[test]
+fn test_parse_mappings_resolves_sources_and_names() {
+    let source_map: SourceMap = serde_json::from_str(
+        r#"{
+            "version": 3,
+            "sources": ["src/app.ts"],
+            "names": ["testFunction"],
+            "mappings": "AAAA,SAASA;;A"
+        }"#,
+    )
+    .unwrap();
+
+    let lines = SourceMapDecoder::parse_mappings(&source_map).unwrap();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0][1].generated_column, 9);
+    assert_eq!(lines[0][1].source.as_deref(), Some("src/app.ts"));
+    assert_eq!(lines[0][1].name.as_deref(), Some("testFunction"));
+    assert!(lines[1].is_empty());
+    assert_eq!(lines[2][0].generated_line, 2);
+    assert_eq!(lines[2][0].source, None);
+}
+
+#[test]
+fn test_index_source_map_sections() {
+    let test_data = r#"{
+        "dist/bundle.js": {
+            "path": "dist/bundle.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 10}},
+                "1": {"start": {"line": 3, "column": 4}, "end": {"line": 3, "column": 14}}
+            },
+            "fnMap": {},
+            "branchMap": {},
+            "s": {"0": 1, "1": 2},
+            "f": {},
+            "b": {},
+            "inputSourceMap": {
+                "version": 3,
+                "file": "bundle.js",
+                "sections": [
+                    {
+                        "offset": {"line": 0, "column": 0},
+                        "map": {"version": 3, "sources": ["../src/a.ts"], "names": [], "mappings": "AAAA"}
+                    },
+                    {
+                        "offset": {"line": 2, "column": 4},
+                        "map": {"version": 3, "sources": ["../src/b.ts"], "names": [], "mappings": "AACA"}
+                    }
+                ]
+            }
+        }
+    }"#;
+
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+    let source_map = coverage_map["dist/bundle.js"]
+        .input_source_map
+        .clone()
+        .unwrap();
+    let decoder = SourceMapDecoder::new(&source_map).unwrap();
+
+    let pos = decoder
+        .get_original_position(3, 4, Bias::GreatestLowerBound)
+        .unwrap();
+    assert_eq!(pos.source, "../src/b.ts");
+    assert_eq!((pos.line, pos.column), (2, 0));
+    assert!(decoder
+        .get_original_position(3, 2, Bias::GreatestLowerBound)
+        .is_none());
+
+    let transformed = SourceMapStore::new()
+        .transform_coverage(coverage_map)
+        .unwrap();
+    assert_eq!(transformed["src/a.ts"].s.get("0"), Some(&1));
+    assert_eq!(transformed["src/b.ts"].s.get("0"), Some(&2));
+    assert_eq!(transformed["src/b.ts"].statement_map["0"].start.line, 2);
+}
+
+#[test]
+fn test_pathutils_join_source_root() {
+    use istanbul_sourcemap::pathutils::{join, normalize, url_to_path};
+
+    assert_eq!(join("/src/", "app.ts"), "/src/app.ts");
+    assert_eq!(join("/src", "./lib/../app.ts"), "/src/app.ts");
+    assert_eq!(join("lib", "app.ts"), "lib/app.ts");
+    assert_eq!(join("/src/", "/abs/app.ts"), "/abs/app.ts");
+    assert_eq!(
+        join("https://example.com/assets/", "/app.ts"),
+        "https://example.com/app.ts"
+    );
+    assert_eq!(
+        join("/src/", "https://example.com/app.ts"),
+        "https://example.com/app.ts"
+    );
+    assert_eq!(join("webpack:///", "./src/app.ts"), "webpack:///src/app.ts");
+    assert_eq!(normalize("../a/./b/../c.ts"), "../a/c.ts");
+
+    assert_eq!(url_to_path("webpack:///src/app.ts"), "src/app.ts");
+    assert_eq!(url_to_path("webpack://my-lib/./src/app.ts"), "src/app.ts");
+    assert_eq!(url_to_path("file:///home/ci/app.ts"), "/home/ci/app.ts");
+    assert_eq!(
+        url_to_path("https://example.com/app.ts"),
+        "https://example.com/app.ts"
+    );
+}
+
+#[test]
+fn test_transform_coverage_honors_source_root() {
+    let coverage = |source_root: &str| {
+        format!(
+            r#"{{
+            "dist/app.js": {{
+                "path": "dist/app.js",
+                "statementMap": {{
+                    "0": {{"start": {{"line": 1, "column": 0}}, "end": {{"line": 1, "column": 10}}}}
+                }},
+                "fnMap": {{}},
+                "branchMap": {{}},
+                "s": {{"0": 1}},
+                "f": {{}},
+                "b": {{}},
+                "inputSourceMap": {{
+                    "version": 3,
+                    "sources": ["app.ts"],
+                    "names": [],
+                    "mappings": "AAAA",
+                    "sourceRoot": "{source_root}"
+                }}
+            }}
+        }}"#
+        )
+    };
+
+    let store = SourceMapStore::new();
+    let transform = |json: String| {
+        let coverage_map: CoverageMap = serde_json::from_str(&json).unwrap();
+        store.transform_coverage(coverage_map).unwrap()
+    };
+
+    assert!(transform(coverage("/src/")).contains_key("/src/app.ts"));
+    assert!(transform(coverage("webpack:///src")).contains_key("src/app.ts"));
+}
+
+#[test]
+fn test_mapped_sources_resolve_against_generated_file() {
+    let source_map: SourceMap = serde_json::from_str(
+        r#"{
+            "version": 3,
+            "sources": ["../src/app.ts", "/abs/lib.ts", "https://example.com/remote.ts", "./util.ts"],
+            "names": [],
+            "mappings": "AAAA;ACAA;ACAA;ACAA"
+        }"#,
+    )
+    .unwrap();
+    let decoder = SourceMapDecoder::new(&source_map).unwrap();
+    let source_for = |line: u32, orig_file: &str| {
+        let loc = Location {
+            start: Position { line, column: 0 },
+            end: Position { line, column: 5 },
+            skip: false,
+        };
+        get_mapping(&decoder, &loc, orig_file).unwrap().source
+    };
+
+    assert_eq!(source_for(1, "dist/app.js"), "src/app.ts");
+    assert_eq!(source_for(1, "/repo/dist/app.js"), "/repo/src/app.ts");
+    assert_eq!(source_for(2, "dist/app.js"), "/abs/lib.ts");
+    assert_eq!(
+        source_for(3, "dist/app.js"),
+        "https://example.com/remote.ts"
+    );
+    assert_eq!(source_for(4, "dist/app.js"), "dist/util.ts");
+    assert_eq!(source_for(4, "app.js"), "util.ts");
+}
+
+#[test]
+fn test_base_dir_resolves_and_relativizes_paths() {
+    let test_data = r#"{
+        "dist/app.js": {
+            "path": "dist/app.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 10}},
+                "1": {"start": {"line": 2, "column": 0}, "end": {"line": 2, "column": 10}}
+            },
+            "fnMap": {},
+            "branchMap": {},
+            "s": {"0": 1, "1": 1},
+            "f": {},
+            "b": {},
+            "inputSourceMap": {
+                "version": 3,
+                "sources": ["../src/app.ts", "/other/lib.ts"],
+                "names": [],
+                "mappings": "AAAA;ACAA"
+            }
+        }
+    }"#;
+
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+    let store = SourceMapStore::with_base_dir("/repo/packages/web".to_string());
+    let transformed = store.transform_coverage(coverage_map).unwrap();
+
+    assert!(transformed.contains_key("src/app.ts"));
+    assert!(transformed.contains_key("/other/lib.ts"));
+
+    use istanbul_sourcemap::pathutils::relative_to_base;
+    assert_eq!(relative_to_base("/repo/", "/repo/src/a.ts"), "src/a.ts");
+    assert_eq!(relative_to_base("/repo", "/repo/src/a.ts"), "src/a.ts");
+    assert_eq!(
+        relative_to_base("/repo", "/repository/a.ts"),
+        "/repository/a.ts"
+    );
+    assert_eq!(relative_to_base("./pkg", "pkg/src/a.ts"), "src/a.ts");
+}
+
+#[test]
+fn test_registered_source_maps() {
+    let coverage = |path: &str| {
+        format!(
+            r#"{{
+            "{path}": {{
+                "path": "{path}",
+                "statementMap": {{
+                    "0": {{"start": {{"line": 1, "column": 0}}, "end": {{"line": 1, "column": 10}}}}
+                }},
+                "fnMap": {{}},
+                "branchMap": {{}},
+                "s": {{"0": 3}},
+                "f": {{}},
+                "b": {{}}
+            }}
+        }}"#
+        )
+    };
+    let source_map = r#"{
+        "version": 3,
+        "sources": ["../src/app.ts"],
+        "names": [],
+        "mappings": "AAAA"
+    }"#;
+
+    let mut store = SourceMapStore::new();
+    store.register_map("./dist/app.js", serde_json::from_str(source_map).unwrap());
+    let coverage_map: CoverageMap = serde_json::from_str(&coverage("dist/app.js")).unwrap();
+    let transformed = store.transform_coverage(coverage_map).unwrap();
+    assert_eq!(transformed["src/app.ts"].s.get("0"), Some(&3));
+
+    let dir = std::env::temp_dir().join(format!("istanbul-sourcemap-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("dist")).unwrap();
+    std::fs::write(dir.join("dist/app.js.map"), source_map).unwrap();
+    let generated = dir.join("dist/app.js").to_string_lossy().into_owned();
+
+    let mut store = SourceMapStore::new();
+    store.register_url(&generated, "app.js.map");
+    let coverage_map: CoverageMap = serde_json::from_str(&coverage(&generated)).unwrap();
+    let transformed = store.transform_coverage(coverage_map).unwrap();
+    let expected = dir.join("src/app.ts").to_string_lossy().into_owned();
+    assert_eq!(transformed[&expected].s.get("0"), Some(&3));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_discover_source_maps_on_disk() {
+    let dir = std::env::temp_dir().join(format!(
+        "istanbul-sourcemap-discover-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(dir.join("dist/maps")).unwrap();
+    std::fs::write(
+        dir.join("dist/app.js"),
+        "var a = 1;\n//# sourceMappingURL=maps/app.js.map?v=1\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("dist/maps/app.js.map"),
+        r#"{"version": 3, "sources": ["../../src/app.ts"], "names": [], "mappings": "AAAA"}"#,
+    )
+    .unwrap();
+    // 不在生成文件目录下的索引source map，各节的源文件相对于map文件
+    std::fs::write(
+        dir.join("dist/index.js"),
+        "var b = 1;\n//# sourceMappingURL=maps/index.js.map\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("dist/maps/index.js.map"),
+        r#"{"version": 3, "sections": [{"offset": {"line": 0, "column": 0}, "map": {"version": 3, "sources": ["../../src/index.ts"], "names": [], "mappings": "AAAA"}}]}"#,
+    )
+    .unwrap();
+
+    let test_data = r#"{
+        "dist/index.js": {
+            "path": "dist/index.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 10}}
+            },
+            "fnMap": {},
+            "branchMap": {},
+            "s": {"0": 2},
+            "f": {},
+            "b": {}
+        },
+        "dist/app.js": {
+            "path": "dist/app.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 10}}
+            },
+            "fnMap": {},
+            "branchMap": {},
+            "s": {"0": 1},
+            "f": {},
+            "b": {}
+        },
+        "dist/missing.js": {
+            "path": "dist/missing.js",
+            "statementMap": {},
+            "fnMap": {},
+            "branchMap": {},
+            "s": {},
+            "f": {},
+            "b": {}
+        }
+    }"#;
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+    let store = SourceMapStore::with_base_dir(dir.to_string_lossy().into_owned())
+        .with_source_map_discovery(true);
+    let transformed = store.transform_coverage(coverage_map).unwrap();
+
+    assert_eq!(transformed["src/app.ts"].s.get("0"), Some(&1));
+    assert_eq!(transformed["src/index.ts"].s.get("0"), Some(&2));
+    assert!(transformed.contains_key("dist/missing.js"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        find_source_mapping_url("x();\n//# sourceMappingURL=a.js.map\n\n"),
+        Some("a.js.map")
+    );
+    assert_eq!(
+        find_source_mapping_url("x();\n//@ sourceMappingURL=b.map#frag"),
+        Some("b.map")
+    );
+    assert_eq!(find_source_mapping_url("x();\n"), None);
+}
+
+#[test]
+fn test_broken_discovered_source_maps_are_diagnosed() {
+    let dir =
+        std::env::temp_dir().join(format!("istanbul-sourcemap-broken-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("dist")).unwrap();
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("dist/app.js"),
+        "var a = 1;\n//# sourceMappingURL=app.js.map\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("dist/app.js.map"),
+        r#"{"version": 3, "sources": ["../src/app.ts"], "names": [], "mappings": "AAAA"}"#,
+    )
+    .unwrap();
+    // 中间文件的source map不是合法JSON
+    std::fs::write(
+        dir.join("src/app.ts"),
+        "const a = 1;\n//# sourceMappingURL=app.ts.map\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("src/app.ts.map"), "{not json").unwrap();
+    std::fs::write(
+        dir.join("dist/bad.js"),
+        "var b = 1;\n//# sourceMappingURL=bad.js.map\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("dist/bad.js.map"), "{not json").unwrap();
+    std::fs::write(
+        dir.join("dist/inline.js"),
+        "var c = 1;\n//# sourceMappingURL=data:application/json;base64,!!!\n",
+    )
+    .unwrap();
+    // sourceMappingURL指向不存在的map文件
+    std::fs::write(
+        dir.join("dist/lost.js"),
+        "var d = 1;\n//# sourceMappingURL=lost.js.map\n",
+    )
+    .unwrap();
+
+    let coverage = |files: &[&str]| {
+        let files: Vec<String> = files
+            .iter()
+            .map(|path| {
+                format!(
+                    r#""{path}": {{
+                        "path": "{path}",
+                        "statementMap": {{
+                            "0": {{"start": {{"line": 1, "column": 0}}, "end": {{"line": 1, "column": 10}}}}
+                        }},
+                        "fnMap": {{}},
+                        "branchMap": {{}},
+                        "s": {{"0": 1}},
+                        "f": {{}},
+                        "b": {{}}
+                    }}"#
+                )
+            })
+            .collect();
+        let coverage_map: CoverageMap =
+            serde_json::from_str(&format!("{{{}}}", files.join(","))).unwrap();
+        coverage_map
+    };
+    let store = SourceMapStore::with_base_dir(dir.to_string_lossy().into_owned())
+        .with_source_map_discovery(true);
+
+    let (transformed, report) = store
+        .transform_coverage_with_report(coverage(&[
+            "dist/app.js",
+            "dist/bad.js",
+            "dist/inline.js",
+            "dist/lost.js",
+        ]))
+        .unwrap();
+    assert_eq!(transformed["src/app.ts"].s["0"], 1);
+    assert!(!transformed.contains_key("dist/bad.js"));
+    // 统计与丢弃列表保持一致
+    let bad = &report.files["dist/bad.js"];
+    assert_eq!(bad.statements.unmappable, 1);
+    assert_eq!(bad.dropped.len(), 1);
+    assert_eq!(bad.dropped[0].kind, EntryKind::Statement);
+    assert_eq!(bad.dropped[0].id, "0");
+    let invalid: Vec<&str> = report
+        .diagnostics
+        .iter()
+        .filter(|d| d.kind == DiagnosticKind::InvalidSourceMap)
+        .map(|d| d.file.as_str())
+        .collect();
+    assert_eq!(invalid.len(), 4);
+    assert!(invalid.contains(&"dist/bad.js"));
+    assert!(invalid.contains(&"dist/lost.js"));
+    assert!(invalid.contains(&"dist/inline.js"));
+    assert!(invalid.iter().any(|file| file.ends_with("src/app.ts")));
+
+    // 严格模式下返回错误
+    let strict = store.with_strict(true);
+    let err = strict
+        .transform_coverage(coverage(&["dist/bad.js"]))
+        .unwrap_err();
+    assert!(matches!(err, Error::InvalidSourceMap { .. }));
+    let err = strict
+        .transform_coverage(coverage(&["dist/inline.js"]))
+        .unwrap_err();
+    assert!(matches!(err, Error::InvalidDataUrl(_)));
+    let err = strict
+        .transform_coverage(coverage(&["dist/lost.js"]))
+        .unwrap_err();
+    assert!(matches!(err, Error::Io { ref path, .. } if path.ends_with("dist/lost.js.map")));
+    let err = strict
+        .transform_coverage(coverage(&["dist/app.js"]))
+        .unwrap_err();
+    assert!(
+        matches!(err, Error::InvalidSourceMap { ref path, .. } if path.ends_with("src/app.ts.map"))
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_inline_data_url_source_maps() {
+    // {"version":3,"sources":["../src/app.ts"],"names":[],"mappings":"AAAA"}
+    let base64_url = "data:application/json;charset=utf-8;base64,eyJ2ZXJzaW9uIjozLCJzb3VyY2VzIjpbIi4uL3NyYy9hcHAudHMiXSwibmFtZXMiOltdLCJtYXBwaW5ncyI6IkFBQUEifQ==";
+    let percent_url = "data:application/json;charset=utf-8,%7B%22version%22%3A3%2C%22sources%22%3A%5B%22..%2Fsrc%2Fapp.ts%22%5D%2C%22names%22%3A%5B%5D%2C%22mappings%22%3A%22AAAA%22%7D";
+
+    for url in [base64_url, percent_url] {
+        let source_map = decode_data_url(url).unwrap();
+        assert_eq!(source_map.sources, vec!["../src/app.ts"]);
+        assert_eq!(source_map.mappings, "AAAA");
+    }
+    assert!(decode_data_url("data:application/json;base64,!!!").is_err());
+
+    let test_data = r#"{
+        "dist/app.js": {
+            "path": "dist/app.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 10}}
+            },
+            "fnMap": {},
+            "branchMap": {},
+            "s": {"0": 1},
+            "f": {},
+            "b": {}
+        }
+    }"#;
+    let mut store = SourceMapStore::new();
+    store.register_url("dist/app.js", base64_url);
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+    let transformed = store.transform_coverage(coverage_map).unwrap();
+    assert_eq!(transformed["src/app.ts"].s.get("0"), Some(&1));
+
+    let dir =
+        std::env::temp_dir().join(format!("istanbul-sourcemap-inline-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("dist")).unwrap();
+    std::fs::write(
+        dir.join("dist/app.js"),
+        format!("var a = 1;\n//# sourceMappingURL={percent_url}\n"),
+    )
+    .unwrap();
+    let store = SourceMapStore::with_base_dir(dir.to_string_lossy().into_owned())
+        .with_source_map_discovery(true);
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+    let transformed = store.transform_coverage(coverage_map).unwrap();
+    assert_eq!(transformed["src/app.ts"].s.get("0"), Some(&1));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_chained_source_maps() {
+    let test_data = r#"{
+        "dist/bundle.js": {
+            "path": "dist/bundle.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 10}},
+                "1": {"start": {"line": 2, "column": 4}, "end": {"line": 2, "column": 12}}
+            },
+            "fnMap": {},
+            "branchMap": {},
+            "s": {"0": 4, "1": 2},
+            "f": {},
+            "b": {},
+            "inputSourceMap": {
+                "version": 3,
+                "sources": ["../build/app.js"],
+                "names": [],
+                "mappings": "AAAA;IACA"
+            }
+        }
+    }"#;
+
+    // Babel输出build/app.js到TypeScript源文件的映射
+    let babel_map: SourceMap = serde_json::from_str(
+        r#"{
+            "version": 3,
+            "sources": ["../src/app.ts"],
+            "names": [],
+            "mappings": "AAAA;IAEA"
+        }"#,
+    )
+    .unwrap();
+
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+    // 相对的base_dir下中间文件的source map同样能找到
+    for mut store in [
+        SourceMapStore::new(),
+        SourceMapStore::with_base_dir("pkg".to_string()),
+    ] {
+        store.register_map("build/app.js", babel_map.clone());
+        let transformed = store.transform_coverage(coverage_map.clone()).unwrap();
+
+        assert_eq!(transformed.len(), 1);
+        let fc = &transformed["src/app.ts"];
+        assert_eq!(fc.s.values().sum::<u32>(), 6);
+        let lines: Vec<u32> = {
+            let mut lines: Vec<u32> = fc.statement_map.values().map(|l| l.start.line).collect();
+            lines.sort();
+            lines
+        };
+        assert_eq!(lines, vec![1, 3]);
+    }
+}
+
+#[test]
+fn test_ignore_list_skips_vendored_sources() {
+    let coverage = |ignore_field: &str| {
+        format!(
+            r#"{{
+            "dist/app.js": {{
+                "path": "dist/app.js",
+                "statementMap": {{
+                    "0": {{"start": {{"line": 1, "column": 0}}, "end": {{"line": 1, "column": 10}}}},
+                    "1": {{"start": {{"line": 2, "column": 0}}, "end": {{"line": 2, "column": 10}}}}
+                }},
+                "fnMap": {{}},
+                "branchMap": {{}},
+                "s": {{"0": 1, "1": 1}},
+                "f": {{}},
+                "b": {{}},
+                "inputSourceMap": {{
+                    "version": 3,
+                    "sources": ["app.ts", "node_modules/lib/index.js"],
+                    "names": [],
+                    "mappings": "AAAA;ACAA",
+                    "{ignore_field}": [1]
+                }}
+            }}
+        }}"#
+        )
+    };
+
+    for field in ["ignoreList", "x_google_ignoreList"] {
+        let coverage_map: CoverageMap = serde_json::from_str(&coverage(field)).unwrap();
+        let source_map = coverage_map["dist/app.js"]
+            .input_source_map
+            .clone()
+            .unwrap();
+        let decoder = SourceMapDecoder::new(&source_map).unwrap();
+        assert!(decoder.is_ignored("node_modules/lib/index.js"));
+        assert!(!decoder.is_ignored("app.ts"));
+
+        let transformed = SourceMapStore::new()
+            .transform_coverage(coverage_map.clone())
+            .unwrap();
+        assert!(transformed.contains_key("dist/node_modules/lib/index.js"));
+
+        let transformed = SourceMapStore::new()
+            .with_ignore_list(true)
+            .transform_coverage(coverage_map.clone())
+            .unwrap();
+        assert!(transformed.contains_key("dist/app.ts"));
+        assert!(!transformed.contains_key("dist/node_modules/lib/index.js"));
+
+        // 严格模式不因ignoreList中的源文件而失败
+        let strict = SourceMapStore::new()
+            .with_ignore_list(true)
+            .with_strict(true);
+        let transformed = strict.transform_coverage(coverage_map).unwrap();
+        assert!(transformed.contains_key("dist/app.ts"));
+        let all_ignored = coverage(field).replace("[1]", "[0, 1]");
+        let coverage_map: CoverageMap = serde_json::from_str(&all_ignored).unwrap();
+        assert!(strict
+            .transform_coverage(coverage_map.clone())
+            .unwrap()
+            .is_empty());
+
+        // 全部属于ignoreList的文件不算无法映射，也不会以生成文件保留
+        for policy in [
+            UnmappedFilePolicy::KeepOriginal,
+            UnmappedFilePolicy::KeepWithMarker,
+        ] {
+            let (transformed, report) = SourceMapStore::new()
+                .with_ignore_list(true)
+                .with_unmapped_files(policy)
+                .transform_coverage_with_report(coverage_map.clone())
+                .unwrap();
+            assert!(transformed.is_empty());
+            assert!(report.diagnostics.is_empty());
+        }
+    }
+}
+
+#[test]
+fn test_sources_content_carried_into_output() {
+    let test_data = r#"{
+        "dist/app.js": {
+            "path": "dist/app.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 10}},
+                "1": {"start": {"line": 2, "column": 0}, "end": {"line": 2, "column": 10}}
+            },
+            "fnMap": {},
+            "branchMap": {},
+            "s": {"0": 1, "1": 1},
+            "f": {},
+            "b": {},
+            "inputSourceMap": {
+                "version": 3,
+                "sources": ["../src/app.ts", "../src/gen.ts"],
+                "sourcesContent": ["const a: number = 1;\n", null],
+                "names": [],
+                "mappings": "AAAA;ACAA"
+            }
+        }
+    }"#;
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+
+    let transformed = SourceMapStore::new()
+        .transform_coverage(coverage_map.clone())
+        .unwrap();
+    assert_eq!(transformed["src/app.ts"].source_content, None);
+
+    let transformed = SourceMapStore::new()
+        .with_sources_content(true)
+        .transform_coverage(coverage_map)
+        .unwrap();
+    assert_eq!(
+        transformed["src/app.ts"].source_content.as_deref(),
+        Some("const a: number = 1;\n")
+    );
+    assert_eq!(transformed["src/gen.ts"].source_content, None);
+
+    let json = serde_json::to_value(&transformed["src/app.ts"]).unwrap();
+    assert_eq!(json["sourceContent"], "const a: number = 1;\n");
+}
+
+#[test]
+fn test_original_function_names() {
+    let test_data = r#"{
+        "dist/app.min.js": {
+            "path": "dist/app.min.js",
+            "statementMap": {},
+            "fnMap": {
+                "0": {
+                    "name": "a",
+                    "decl": {"start": {"line": 1, "column": 9}, "end": {"line": 1, "column": 10}},
+                    "loc": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 20}}
+                },
+                "1": {
+                    "name": "(anonymous_1)",
+                    "decl": {"start": {"line": 2, "column": 0}, "end": {"line": 2, "column": 8}},
+                    "loc": {"start": {"line": 2, "column": 0}, "end": {"line": 2, "column": 20}}
+                },
+                "2": {
+                    "name": "(anonymous_2)",
+                    "decl": {"start": {"line": 3, "column": 7}, "end": {"line": 3, "column": 15}},
+                    "loc": {"start": {"line": 3, "column": 7}, "end": {"line": 3, "column": 19}}
+                }
+            },
+            "branchMap": {},
+            "s": {},
+            "f": {"0": 1, "1": 0, "2": 0},
+            "b": {},
+            "inputSourceMap": {
+                "version": 3,
+                "sources": ["../src/app.ts"],
+                "names": ["calculateTotal", "call"],
+                "mappings": "AAAA,SAASA;AACT;EACAC"
+            }
+        }
+    }"#;
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+    let names = |fc: &FileCoverage| {
+        let mut names: Vec<String> = fc.fn_map.values().map(|f| f.name.clone()).collect();
+        names.sort();
+        names
+    };
+
+    let transformed = SourceMapStore::new()
+        .transform_coverage(coverage_map.clone())
+        .unwrap();
+    // x.call(function(){})：匿名函数只落在`call`片段内，不使用该名称
+    assert_eq!(
+        names(&transformed["src/app.ts"]),
+        vec!["(anonymous_1)", "(anonymous_2)", "calculateTotal"]
+    );
+
+    let transformed = SourceMapStore::new()
+        .with_function_names(FunctionNames::Generated)
+        .transform_coverage(coverage_map)
+        .unwrap();
+    assert_eq!(
+        names(&transformed["src/app.ts"]),
+        vec!["(anonymous_1)", "(anonymous_2)", "a"]
+    );
+}
+
+#[test]
+fn test_typed_errors() {
+    let source_map = |version: u32, mappings: &str| SourceMap {
+        version,
+        sources: vec!["a.ts".to_string()],
+        names: vec![],
+        mappings: mappings.to_string(),
+        file: None,
+        source_root: None,
+        sources_content: None,
+        ignore_list: None,
+        x_google_ignore_list: None,
+        sections: None,
+    };
+
+    assert!(matches!(
+        SourceMapDecoder::new(&source_map(2, "AAAA")),
+        Err(Error::UnsupportedVersion(2))
+    ));
+    match SourceMapDecoder::new(&source_map(3, "AAAA;AAAA,AAAg")) {
+        Err(Error::Vlq {
+            line,
+            index,
+            segment,
+        }) => assert_eq!((line, index, segment.as_str()), (1, 1, "AAAg")),
+        other => panic!("expected a VLQ error, got {:?}", other.err()),
+    }
+    assert!(matches!(
+        decode_data_url("data:application/json;base64,!!!"),
+        Err(Error::InvalidDataUrl(_))
+    ));
+    assert!(matches!(
+        transform_istanbul_coverage("{not json"),
+        Err(Error::InvalidJson(_))
+    ));
+
+    let mut store = SourceMapStore::new();
+    store.register_url("dist/app.js", "/nonexistent/app.js.map");
+    let coverage = r#"{"dist/app.js": {"path": "dist/app.js", "statementMap": {}, "fnMap": {}, "branchMap": {}, "s": {}, "f": {}, "b": {}}}"#;
+    let coverage_map: CoverageMap = serde_json::from_str(coverage).unwrap();
+    let (_, report) = store
+        .transform_coverage_with_report(coverage_map.clone())
+        .unwrap();
+    assert_eq!(report.diagnostics[0].kind, DiagnosticKind::InvalidSourceMap);
+    let err = store
+        .with_strict(true)
+        .transform_coverage(coverage_map)
+        .unwrap_err();
+    assert!(matches!(err, Error::Io { ref path, .. } if path == "/nonexistent/app.js.map"));
+    assert_eq!(err.code(), 7);
+}
+
+#[test]
+fn test_transform_report_counts_and_diagnostics() {
+    let test_data = r#"{
+        "dist/app.js": {
+            "path": "dist/app.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 10}},
+                "1": {"start": {"line": 5, "column": 0}, "end": {"line": 5, "column": 10}}
+            },
+            "fnMap": {},
+            "branchMap": {},
+            "s": {"0": 1, "1": 0},
+            "f": {},
+            "b": {},
+            "inputSourceMap": {
+                "version": 3,
+                "sources": ["../src/app.ts"],
+                "names": [],
+                "mappings": "AAAA"
+            }
+        },
+        "dist/empty.js": {
+            "path": "dist/empty.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 10}}
+            },
+            "fnMap": {},
+            "branchMap": {},
+            "s": {"0": 1},
+            "f": {},
+            "b": {},
+            "inputSourceMap": {
+                "version": 3,
+                "sources": ["../src/empty.ts"],
+                "names": [],
+                "mappings": ""
+            }
+        }
+    }"#;
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+    let (transformed, report) = SourceMapStore::new()
+        .transform_coverage_with_report(coverage_map)
+        .unwrap();
+
+    assert_eq!(transformed.len(), 1);
+    assert_eq!(
+        report.files["dist/app.js"].statements,
+        MappingCounts {
+            mapped: 1,
+            unmappable: 1,
+            ..MappingCounts::default()
+        }
+    );
+    assert_eq!(report.files["dist/empty.js"].mapped(), 0);
+    assert_eq!(report.diagnostics.len(), 1);
+    assert_eq!(report.diagnostics[0].kind, DiagnosticKind::FileIgnored);
+    assert_eq!(report.diagnostics[0].file, "dist/empty.js");
+}
+
+#[test]
+fn test_transform_report_drop_reasons_and_merges() {
+    let test_data = r#"{
+        "dist/bundle.js": {
+            "path": "dist/bundle.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 3}},
+                "1": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 3}},
+                "2": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 8}},
+                "3": {"start": {"line": 9, "column": 0}, "end": {"line": 9, "column": 1}}
+            },
+            "fnMap": {},
+            "branchMap": {},
+            "s": {"0": 1, "1": 2, "2": 1, "3": 0},
+            "f": {},
+            "b": {},
+            "inputSourceMap": {
+                "version": 3,
+                "sources": ["../src/a.ts", "../src/b.ts"],
+                "names": [],
+                "mappings": "AAAA,KCAA"
+            }
+        }
+    }"#;
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+    let (transformed, report) = SourceMapStore::new()
+        .transform_coverage_with_report(coverage_map)
+        .unwrap();
+
+    let statements = report.files["dist/bundle.js"].statements;
+    assert_eq!(
+        statements,
+        MappingCounts {
+            mapped: 2,
+            unmappable: 1,
+            multi_source: 1,
+            ignored: 0,
+            merged: 1,
+            placeholder_arms: 0,
+        }
+    );
+    assert_eq!(statements.dropped(), 2);
+    assert_eq!(transformed["src/a.ts"].s["0"], 3);
+}
+
+#[test]
+fn test_strict_mode_fails_on_unmapped_coverage() {
+    let coverage = |mappings: &str| {
+        let test_data = format!(
+            r#"{{
+            "dist/app.js": {{
+                "path": "dist/app.js",
+                "statementMap": {{
+                    "0": {{"start": {{"line": 1, "column": 0}}, "end": {{"line": 1, "column": 10}}}},
+                    "1": {{"start": {{"line": 5, "column": 2}}, "end": {{"line": 5, "column": 10}}}}
+                }},
+                "fnMap": {{}},
+                "branchMap": {{}},
+                "s": {{"0": 1, "1": 0}},
+                "f": {{}},
+                "b": {{}},
+                "inputSourceMap": {{
+                    "version": 3,
+                    "sources": ["../src/app.ts"],
+                    "names": [],
+                    "mappings": "{mappings}"
+                }}
+            }}
+        }}"#
+        );
+        serde_json::from_str::<CoverageMap>(&test_data).unwrap()
+    };
+
+    assert!(SourceMapStore::new()
+        .transform_coverage(coverage("AAAA"))
+        .is_ok());
+
+    let strict = SourceMapStore::new().with_strict(true);
+    match strict.transform_coverage(coverage("AAAA")) {
+        Err(Error::UnmappedEntry {
+            path,
+            kind,
+            id,
+            line,
+            column,
+            reason,
+        }) => {
+            assert_eq!(path, "dist/app.js");
+            assert_eq!(kind, EntryKind::Statement);
+            assert_eq!(id, "1");
+            assert_eq!((line, column), (5, 2));
+            assert_eq!(reason, DropReason::Unmappable);
+        }
+        other => panic!("expected an unmapped entry error, got {other:?}"),
+    }
+    assert!(matches!(
+        strict.transform_coverage(coverage("")),
+        Err(Error::UnmappableFile { path }) if path == "dist/app.js"
+    ));
+
+    // 没有任何条目的文件（如只含类型的模块）不算无法映射
+    let mut empty = coverage("");
+    let fc = empty.get_mut("dist/app.js").unwrap();
+    fc.statement_map.clear();
+    fc.s.clear();
+    assert!(strict.transform_coverage(empty).unwrap().is_empty());
+}
+
+#[test]
+fn test_unmapped_file_policy() {
+    let test_data = r#"{
+        "dist/empty.js": {
+            "path": "dist/empty.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 10}}
+            },
+            "fnMap": {},
+            "branchMap": {},
+            "s": {"0": 1},
+            "f": {},
+            "b": {},
+            "inputSourceMap": {
+                "version": 3,
+                "sources": ["../src/empty.ts"],
+                "names": [],
+                "mappings": ""
+            }
+        }
+    }"#;
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+    let transform = |policy| {
+        SourceMapStore::new()
+            .with_unmapped_files(policy)
+            .transform_coverage_with_report(coverage_map.clone())
+            .unwrap()
+    };
+
+    let (dropped, report) = transform(UnmappedFilePolicy::Drop);
+    assert!(dropped.is_empty());
+    assert_eq!(report.diagnostics[0].kind, DiagnosticKind::FileIgnored);
+
+    let (kept, report) = transform(UnmappedFilePolicy::KeepOriginal);
+    assert_eq!(kept["dist/empty.js"].s["0"], 1);
+    assert!(!kept["dist/empty.js"].unmapped);
+    assert_eq!(report.diagnostics[0].kind, DiagnosticKind::FileIgnored);
+
+    let (marked, _) = transform(UnmappedFilePolicy::KeepWithMarker);
+    assert!(marked["dist/empty.js"].unmapped);
+    let json = serde_json::to_value(&marked).unwrap();
+    assert_eq!(json["dist/empty.js"]["unmapped"], true);
+    let json = serde_json::to_value(&kept).unwrap();
+    assert!(json["dist/empty.js"].get("unmapped").is_none());
+}
+
+#[test]
+fn test_branch_arms_stay_aligned_with_hits() {
+    let test_data = r#"{
+        "dist/app.js": {
+            "path": "dist/app.js",
+            "statementMap": {},
+            "fnMap": {},
+            "branchMap": {
+                "0": {
+                    "type": "if",
+                    "loc": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 10}},
+                    "locations": [
+                        {"start": {"line": 2, "column": 0}, "end": {"line": 2, "column": 10}},
+                        {"start": {"line": 7, "column": 0}, "end": {"line": 7, "column": 10}}
+                    ]
+                }
+            },
+            "s": {},
+            "f": {},
+            "b": {"0": [3, 5]},
+            "inputSourceMap": {
+                "version": 3,
+                "sources": ["../src/app.ts"],
+                "names": [],
+                "mappings": "AAAA;AACA"
+            }
+        }
+    }"#;
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+
+    let (transformed, report) = SourceMapStore::new()
+        .transform_coverage_with_report(coverage_map.clone())
+        .unwrap();
+    let fc = &transformed["src/app.ts"];
+    let branch = &fc.branch_map["0"];
+    assert_eq!(fc.b["0"], vec![3, 5]);
+    assert_eq!(branch.locations.len(), 2);
+    assert_eq!(branch.locations[0].start.line, 2);
+    assert_eq!(branch.locations[1], branch.loc);
+    // 占位分支臂需计入报告
+    let file_report = &report.files["dist/app.js"];
+    assert_eq!(file_report.branches.mapped, 1);
+    assert_eq!(file_report.branches.placeholder_arms, 1);
+    assert_eq!(file_report.placeholder_arms[0].id, "0");
+    assert_eq!(file_report.placeholder_arms[0].arm, 1);
+    assert_eq!(file_report.placeholder_arms[0].loc.start.line, 7);
+    let json = serde_json::to_value(file_report).unwrap();
+    assert_eq!(json["branches"]["placeholderArms"], 1);
+
+    // 严格模式下占位分支臂视为无法映射
+    let err = SourceMapStore::new()
+        .with_strict(true)
+        .transform_coverage(coverage_map.clone())
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        Error::UnmappedEntry {
+            kind: EntryKind::Branch,
+            line: 7,
+            reason: DropReason::Unmappable,
+            ..
+        }
+    ));
+
+    let (transformed, report) = SourceMapStore::new()
+        .with_branch_arms(BranchArmPolicy::DropBranch)
+        .transform_coverage_with_report(coverage_map)
+        .unwrap();
+    assert!(transformed.is_empty());
+    assert_eq!(report.files["dist/app.js"].branches.unmappable, 1);
+}
+
+#[test]
+fn test_coverage_schema_round_trip() {
+    let test_data = r#"{
+        "/src/app.js": {
+            "path": "/src/app.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 10}},
+                "1": {"start": {"line": 2, "column": 0}, "end": {"line": 2, "column": 10}, "skip": true}
+            },
+            "fnMap": {
+                "0": {
+                    "name": "run",
+                    "decl": {"start": {"line": 1, "column": 9}, "end": {"line": 1, "column": 12}},
+                    "loc": {"start": {"line": 1, "column": 0}, "end": {"line": 3, "column": 1}},
+                    "line": 1,
+                    "skip": true
+                }
+            },
+            "branchMap": {
+                "0": {
+                    "type": "if",
+                    "loc": {"start": {"line": 2, "column": 0}, "end": {"line": 2, "column": 20}},
+                    "locations": [
+                        {"start": {"line": 2, "column": 0}, "end": {"line": 2, "column": 20}},
+                        {"start": {"line": 2, "column": 0}, "end": {"line": 2, "column": 20}}
+                    ],
+                    "line": 2
+                }
+            },
+            "s": {"0": 1, "1": 0},
+            "f": {"0": 1},
+            "b": {"0": [1, 0]},
+            "all": false,
+            "hash": "1c8a4e1d7b1c1b6f0d1c2e3f4a5b6c7d8e9f0a1b",
+            "_coverageSchema": "1a1c01bbd47fc00a2c39e90264f33305004495a9",
+            "x-custom": {"runner": "vitest"}
+        }
+    }"#;
+
+    let output = transform_istanbul_coverage(test_data).unwrap();
+    let input: serde_json::Value = serde_json::from_str(test_data).unwrap();
+    let output: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(input, output);
+}
+
+#[test]
+fn test_mapped_coverage_keeps_skip_and_line() {
+    let test_data = r#"{
+        "dist/app.js": {
+            "path": "dist/app.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 10}, "skip": true}
+            },
+            "fnMap": {
+                "0": {
+                    "name": "run",
+                    "decl": {"start": {"line": 2, "column": 0}, "end": {"line": 2, "column": 5}},
+                    "loc": {"start": {"line": 2, "column": 0}, "end": {"line": 2, "column": 10}}
+                },
+                "1": {
+                    "name": "ignored",
+                    "decl": {"start": {"line": 3, "column": 0}, "end": {"line": 3, "column": 5}},
+                    "loc": {"start": {"line": 3, "column": 0}, "end": {"line": 3, "column": 10}},
+                    "skip": true
+                }
+            },
+            "branchMap": {
+                "0": {
+                    "type": "if",
+                    "loc": {"start": {"line": 3, "column": 0}, "end": {"line": 3, "column": 10}},
+                    "locations": [
+                        {"start": {"line": 3, "column": 0}, "end": {"line": 3, "column": 10}},
+                        {"start": {"line": 3, "column": 0}, "end": {"line": 3, "column": 10}}
+                    ],
+                    "skip": true
+                }
+            },
+            "s": {"0": 0},
+            "f": {"0": 1, "1": 0},
+            "b": {"0": [0, 0]},
+            "inputSourceMap": {
+                "version": 3,
+                "sources": ["../src/app.ts"],
+                "names": [],
+                "mappings": "AAAA;AAEA;AACA"
+            }
+        }
+    }"#;
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+    let transformed = SourceMapStore::new()
+        .transform_coverage(coverage_map)
+        .unwrap();
+    let fc = &transformed["src/app.ts"];
+
+    assert!(fc.statement_map["0"].skip);
+    assert_eq!(fc.fn_map["0"].line, Some(3));
+    // 函数和分支条目上的skip在映射后保留
+    assert!(!fc.fn_map["0"].skip);
+    assert!(fc.fn_map["1"].skip);
+    assert!(fc.branch_map["0"].skip);
+    let json = serde_json::to_value(fc).unwrap();
+    assert!(json["fnMap"]["0"].get("skip").is_none());
+    assert_eq!(json["fnMap"]["1"]["skip"], true);
+    assert_eq!(json["branchMap"]["0"]["skip"], true);
+}
+
+#[test]
+fn test_branch_truthiness_merged_with_branches() {
+    let file = |path: &str, b: &str, b_t: &str| {
+        format!(
+            r#""{path}": {{
+                "path": "{path}",
+                "statementMap": {{}},
+                "fnMap": {{}},
+                "branchMap": {{
+                    "0": {{
+                        "type": "binary-expr",
+                        "loc": {{"start": {{"line": 1, "column": 0}}, "end": {{"line": 1, "column": 10}}}},
+                        "locations": [
+                            {{"start": {{"line": 1, "column": 0}}, "end": {{"line": 1, "column": 4}}}},
+                            {{"start": {{"line": 1, "column": 5}}, "end": {{"line": 1, "column": 10}}}}
+                        ]
+                    }}
+                }},
+                "s": {{}},
+                "f": {{}},
+                "b": {{"0": {b}}},
+                "bT": {{"0": {b_t}}},
+                "inputSourceMap": {{
+                    "version": 3,
+                    "sources": ["../src/app.ts"],
+                    "names": [],
+                    "mappings": "AAAA,IAAI,KAAK"
+                }}
+            }}"#
+        )
+    };
+    let test_data = format!(
+        "{{{}, {}}}",
+        file("dist/a.js", "[3, 2]", "[1, 2]"),
+        file("dist/b.js", "[1, 0]", "[1, 0]")
+    );
+    let coverage_map: CoverageMap = serde_json::from_str(&test_data).unwrap();
+    let transformed = SourceMapStore::new()
+        .transform_coverage(coverage_map)
+        .unwrap();
+    let fc = &transformed["src/app.ts"];
+
+    assert_eq!(fc.b["0"], vec![4, 2]);
+    assert_eq!(fc.b_t.as_ref().unwrap()["0"], vec![2, 2]);
+    let json = serde_json::to_value(fc).unwrap();
+    assert_eq!(json["bT"]["0"], serde_json::json!([2, 2]));
+}
+
+#[test]
+fn test_missing_and_null_end_columns() {
+    let test_data = r#"{
+        "dist/app.js": {
+            "path": "dist/app.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": null}},
+                "1": {"start": {"line": 2, "column": 0}, "end": {"line": 2}}
+            },
+            "fnMap": {},
+            "branchMap": {
+                "0": {
+                    "type": "if",
+                    "loc": {"start": {"line": 1, "column": 0}, "end": {"line": 2, "column": 4}},
+                    "locations": [
+                        {"start": {"line": 1, "column": 0}},
+                        {"start": {"line": 5}}
+                    ]
+                }
+            },
+            "s": {"0": 1, "1": 1},
+            "f": {},
+            "b": {"0": [1, 0]},
+            "inputSourceMap": {
+                "version": 3,
+                "sources": ["../src/app.ts"],
+                "names": [],
+                "mappings": "AAAA,KAAK;AACA"
+            }
+        }
+    }"#;
+
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+    let branch = &coverage_map["dist/app.js"].branch_map["0"];
+    assert_eq!(branch.locations[0].end.line, 1);
+    assert_eq!(branch.locations[0].end.column, Position::END_OF_LINE);
+    assert_eq!(branch.locations[1].start.column, Position::END_OF_LINE);
+    assert_eq!(branch.locations[1].end.line, 5);
+
+    // `start`与`line`仍为必填字段
+    for loc in [
+        r#"{}"#,
+        r#"{"end": {"line": 1, "column": 0}}"#,
+        r#"{"start": {"column": 0}}"#,
+    ] {
+        assert!(serde_json::from_str::<Location>(loc).is_err(), "{loc}");
+    }
+
+    let transformed = SourceMapStore::new()
+        .transform_coverage(coverage_map)
+        .unwrap();
+    let fc = &transformed["src/app.ts"];
+    let mut ends: Vec<(u32, u32)> = fc
+        .statement_map
+        .values()
+        .map(|loc| (loc.end.line, loc.end.column))
+        .collect();
+    ends.sort();
+    assert_eq!(
+        ends,
+        vec![(1, Position::END_OF_LINE), (2, Position::END_OF_LINE)]
+    );
+    let branch = &fc.branch_map["0"];
+    assert_eq!(fc.b["0"], vec![1, 0]);
+    assert_eq!(branch.locations[0].end.column, Position::END_OF_LINE);
+    assert_eq!(branch.locations[1], branch.loc);
+
+    assert!(transform_istanbul_coverage(test_data).is_ok());
+}
+
+#[test]
+fn test_deterministic_sorted_output() {
+    // 生成代码第i行映射到源码第13-i行，语句按源码位置重新编号
+    let statements: Vec<String> = (1..=12)
+        .map(|line| {
+            format!(
+                r#""{}": {{"start": {{"line": {line}, "column": 0}}, "end": {{"line": {line}, "column": 5}}}}"#,
+                line - 1
+            )
+        })
+        .collect();
+    let hits: Vec<String> = (1..=12)
+        .map(|line| format!(r#""{}": {line}"#, line - 1))
+        .collect();
+    let mappings = std::iter::once("AAWA")
+        .chain(std::iter::repeat_n("AADA", 11))
+        .collect::<Vec<_>>()
+        .join(";");
+    let test_data = format!(
+        r#"{{
+            "dist/b.js": {{
+                "path": "dist/b.js",
+                "statementMap": {{{}}},
+                "fnMap": {{}},
+                "branchMap": {{}},
+                "s": {{{}}},
+                "f": {{}},
+                "b": {{}},
+                "inputSourceMap": {{
+                    "version": 3,
+                    "sources": ["../src/b.ts"],
+                    "names": [],
+                    "mappings": "{mappings}"
+                }}
+            }},
+            "dist/a.js": {{
+                "path": "dist/a.js",
+                "statementMap": {{}},
+                "fnMap": {{}},
+                "branchMap": {{}},
+                "s": {{}},
+                "f": {{}},
+                "b": {{}}
+            }}
+        }}"#,
+        statements.join(","),
+        hits.join(",")
+    );
+
+    let output = transform_istanbul_coverage(&test_data).unwrap();
+    for _ in 0..5 {
+        assert_eq!(transform_istanbul_coverage(&test_data).unwrap(), output);
+    }
+
+    assert!(output.find("dist/a.js").unwrap() < output.find("src/b.ts").unwrap());
+    let positions: Vec<usize> = (0..12)
+        .map(|i| output.find(&format!(r#""{i}": {{"#)).unwrap())
+        .collect();
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+
+    let transformed: CoverageMap = serde_json::from_str(&output).unwrap();
+    let fc = &transformed["src/b.ts"];
+    for i in 0..12 {
+        assert_eq!(fc.statement_map[&i.to_string()].start.line, i + 1);
+        assert_eq!(fc.s[&i.to_string()], 12 - i);
+    }
+}
+
+#[test]
+fn test_result_alias_keeps_error_parameter() {
+    fn parse(input: &str) -> Result<u32, std::num::ParseIntError> {
+        input.parse()
+    }
+    let transformed: Result<String> = transform_istanbul_coverage("{}");
+
+    assert_eq!(parse("7"), Ok(7));
+    assert!(transformed.is_ok());
+}
+
+#[test]
+fn test_ffi_reports_error_codes() {
+    use istanbul_sourcemap::ffi::{
+        free_string, get_last_error_code, transform_coverage_ffi,
+        transform_coverage_with_error_ffi, ERROR_INVALID_UTF8, ERROR_NULL_INPUT,
+    };
+    use std::ffi::{CStr, CString};
+    use std::os::raw::{c_char, c_int};
+
+    let transform = |input: *const c_char| unsafe {
+        let mut code: c_int = -1;
+        let mut message: *mut c_char = std::ptr::null_mut();
+        let result = transform_coverage_with_error_ffi(input, &mut code, &mut message);
+        let message_text =
+            (!message.is_null()).then(|| CStr::from_ptr(message).to_string_lossy().into_owned());
+        free_string(result);
+        free_string(message);
+        (result.is_null(), code, message_text)
+    };
+
+    let valid = CString::new("{}").unwrap();
+    assert_eq!(transform(valid.as_ptr()), (false, 0, None));
+
+    let (failed, code, message) = transform(std::ptr::null());
+    assert!(failed && message.is_some());
+    assert_eq!(code, ERROR_NULL_INPUT);
+
+    let invalid_utf8 = CString::new(vec![b'{', 0xff, b'}']).unwrap();
+    let (_, code, _) = transform(invalid_utf8.as_ptr());
+    assert_eq!(code, ERROR_INVALID_UTF8);
+
+    let invalid_json = CString::new("{not json").unwrap();
+    let (failed, code, message) = transform(invalid_json.as_ptr());
+    assert!(failed);
+    assert_eq!(code, 1);
+    assert!(message.unwrap().starts_with("Invalid JSON"));
+
+    unsafe {
+        assert!(transform_coverage_ffi(std::ptr::null()).is_null());
+    }
+    assert_eq!(get_last_error_code(), ERROR_NULL_INPUT);
+}
+
+#[test]
+fn test_negative_vlq_values_are_rejected() {
+    let source_map = |mappings: &str| SourceMap {
+        version: 3,
+        sources: vec!["a.ts".to_string()],
+        names: vec![],
+        mappings: mappings.to_string(),
+        file: None,
+        source_root: None,
+        sources_content: None,
+        ignore_list: None,
+        x_google_ignore_list: None,
+        sections: None,
+    };
+
+    // 原始行、原始列、源索引和生成列分别变为负数
+    for (mappings, line, index) in [
+        ("AADA", 0, 0),
+        ("AAAA;AAAD", 1, 0),
+        ("AAAA,ADAA", 0, 1),
+        ("AAAA,DAAA", 0, 1),
+    ] {
+        match SourceMapDecoder::new(&source_map(mappings)) {
+            Err(Error::Vlq {
+                line: error_line,
+                index: error_index,
+                ..
+            }) => assert_eq!((error_line, error_index), (line, index), "{mappings}"),
+            other => panic!("expected a VLQ error for {mappings}, got {:?}", other.err()),
+        }
+    }
+
+    let test_data = r#"{
+        "dist/app.js": {
+            "path": "dist/app.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 0}, "end": {"line": 1, "column": 10}}
+            },
+            "fnMap": {},
+            "branchMap": {},
+            "s": {"0": 1},
+            "f": {},
+            "b": {},
+            "inputSourceMap": {
+                "version": 3,
+                "sources": ["../src/app.ts"],
+                "names": [],
+                "mappings": "AADA"
+            }
+        }
+    }"#;
+    assert!(transform_istanbul_coverage(test_data).is_ok());
+    assert!(matches!(
+        SourceMapStore::new()
+            .with_strict(true)
+            .transform_coverage(serde_json::from_str(test_data).unwrap()),
+        Err(Error::Vlq { .. })
+    ));
+}
+
+#[test]
+fn test_map_listing_its_own_generated_file() {
+    // 原地压缩：dist/app.js的source map以app.js自身作为源文件，
+    // 每列向左移动一列，重复应用会不断左移
+    let minify_map: SourceMap = serde_json::from_str(
+        r#"{
+            "version": 3,
+            "sources": ["app.js"],
+            "names": [],
+            "mappings": "CAAA,CAAC,CAAC,CAAC,CAAC,CAAC,CAAC,CAAC,CAAC,CAAC"
+        }"#,
+    )
+    .unwrap();
+    let test_data = r#"{
+        "dist/app.js": {
+            "path": "dist/app.js",
+            "statementMap": {
+                "0": {"start": {"line": 1, "column": 4}, "end": {"line": 1, "column": 9}}
+            },
+            "fnMap": {},
+            "branchMap": {},
+            "s": {"0": 1},
+            "f": {},
+            "b": {}
+        }
+    }"#;
+
+    let mut store = SourceMapStore::new();
+    store.register_map("dist/app.js", minify_map);
+    let coverage_map: CoverageMap = serde_json::from_str(test_data).unwrap();
+    let transformed = store.transform_coverage(coverage_map).unwrap();
+
+    let loc = &transformed["dist/app.js"].statement_map["0"];
+    assert_eq!(
+        (
+            loc.start.line,
+            loc.start.column,
+            loc.end.line,
+            loc.end.column
+        ),
+        (1, 3, 1, 8)
+    );
+}